@@ -1,6 +1,7 @@
 // Copyright (c) 2024-2025 David Stanek <dstanek@dstanek.com>
 
 use std::convert::From;
+use std::fmt;
 use std::io;
 
 use thiserror::Error;
@@ -16,6 +17,12 @@ pub enum StencilError {
     #[error("{0}")]
     Other(String),
 
+    #[error("Rendering error: {0}")]
+    Render(RenderFailure),
+
+    #[error("{} files failed to render\n\n{}", .0.len(), join(.0))]
+    Renders(Vec<RenderFailure>),
+
     // configuration errors
     #[error("Deserialization error:{0}")]
     TomlDeserialization(#[from] toml::de::Error),
@@ -34,6 +41,67 @@ impl StencilError {
     pub fn new(msg: &str) -> Self {
         StencilError::Other(msg.to_string())
     }
+
+    /// A render error with no location, like one in a computed variable.
+    pub fn render(template: &str, message: &str) -> Self {
+        StencilError::Render(RenderFailure {
+            template: template.to_string(),
+            message: message.to_string(),
+            ..RenderFailure::default()
+        })
+    }
+}
+
+/// A template that failed to render, with the line it failed on when that is
+/// known.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenderFailure {
+    /// The template's path, or what was being rendered.
+    pub template: String,
+    pub message: String,
+    pub line: Option<usize>,
+    /// The column the error starts at, counted in characters from 1.
+    pub column: Option<usize>,
+    /// How many characters from `column` the error spans.
+    pub width: usize,
+    /// The text of the line the error is on.
+    pub snippet: Option<String>,
+}
+
+impl fmt::Display for RenderFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.template)?;
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+            if let Some(column) = self.column {
+                write!(f, ":{column}")?;
+            }
+        }
+        write!(f, ": {}", self.message)?;
+
+        let (Some(line), Some(snippet)) = (self.line, &self.snippet) else {
+            return Ok(());
+        };
+        let gutter = " ".repeat(line.to_string().len());
+        write!(f, "\n{gutter} |\n{line} | {snippet}")?;
+        if let Some(column) = self.column {
+            let marker = "^".repeat(self.width.max(1));
+            write!(
+                f,
+                "\n{gutter} | {}{marker}",
+                " ".repeat(column.saturating_sub(1))
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn join(failures: &[RenderFailure]) -> String {
+    failures
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n\n")
 }
 
 impl From<io::Error> for StencilError {
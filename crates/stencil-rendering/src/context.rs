@@ -0,0 +1,98 @@
+// Copyright (c) 2025 David Stanek <dstanek@dstanek.com>
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Utc};
+use minijinja::value::Value;
+use minijinja::Environment;
+
+use crate::new_err;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+/// Registers `env(name, default)` and `now(format)`.
+///
+/// `environment` holds the variables templates are allowed to read, with
+/// `None` for the ones that aren't set. `now` is fixed for the whole render so
+/// every file agrees on the time.
+pub(crate) fn register(
+    env: &mut Environment,
+    environment: Arc<BTreeMap<String, Option<String>>>,
+    now: DateTime<Utc>,
+) {
+    env.add_function(
+        "env",
+        move |name: &str, default: Option<Value>| match environment.get(name) {
+            Some(Some(value)) => Ok(Value::from(value.clone())),
+            Some(None) => Ok(default.unwrap_or(Value::from(()))),
+            None => Err(new_err(&format!(
+                "env: '{name}' isn't in the stencil's environment allowlist"
+            ))),
+        },
+    );
+    env.add_function("now", move |format: Option<&str>| {
+        let format = format.unwrap_or(DEFAULT_DATE_FORMAT);
+        let items: Vec<Item> = StrftimeItems::new(format).collect();
+        if items.contains(&Item::Error) {
+            return Err(new_err(&format!("now: invalid format '{format}'")));
+        }
+        Ok(now.format_with_items(items.into_iter()).to_string())
+    });
+}
+
+/// Registers `dest_file(path)`, `dest_toml(path)` and `dest_json(path)`, which
+/// return a file from the destination as text or parsed data. They return
+/// none when the file doesn't exist, or when there is no destination yet.
+pub(crate) fn register_dest(env: &mut Environment, dest: Option<Arc<PathBuf>>) {
+    let root = dest.clone();
+    env.add_function("dest_file", move |path: &str| {
+        read_dest(root.as_deref(), "dest_file", path).map(Value::from)
+    });
+    let root = dest.clone();
+    env.add_function("dest_toml", move |path: &str| {
+        let Some(contents) = read_dest(root.as_deref(), "dest_toml", path)? else {
+            return Ok(Value::from(()));
+        };
+        let value: toml::Table =
+            toml::from_str(&contents).map_err(|e| new_err(&format!("dest_toml: {path}: {e}")))?;
+        Ok(Value::from_serialize(value))
+    });
+    env.add_function("dest_json", move |path: &str| {
+        let Some(contents) = read_dest(dest.as_deref(), "dest_json", path)? else {
+            return Ok(Value::from(()));
+        };
+        let value: serde_json::Value = serde_json::from_str(&contents)
+            .map_err(|e| new_err(&format!("dest_json: {path}: {e}")))?;
+        Ok(Value::from_serialize(value))
+    });
+}
+
+// Reads a file relative to the destination, which templates can't leave
+fn read_dest(
+    root: Option<&PathBuf>,
+    function: &str,
+    path: &str,
+) -> Result<Option<String>, minijinja::Error> {
+    let relative = Path::new(path);
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(new_err(&format!(
+            "{function}: '{path}' must be relative to the destination"
+        )));
+    }
+    let Some(root) = root else {
+        return Ok(None);
+    };
+    match fs::read_to_string(root.join(relative)) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(new_err(&format!("{function}: {path}: {e}"))),
+    }
+}
@@ -0,0 +1,388 @@
+// Copyright (c) 2025 David Stanek <dstanek@dstanek.com>
+
+use minijinja::Environment;
+
+/// Registers the case-conversion, inflection and identifier filters.
+pub(crate) fn register(env: &mut Environment) {
+    env.add_filter("snake_case", snake_case);
+    env.add_filter("kebab_case", kebab_case);
+    env.add_filter("pascal_case", pascal_case);
+    env.add_filter("camel_case", camel_case);
+    env.add_filter("screaming_snake_case", screaming_snake_case);
+    env.add_filter("slugify", slugify);
+    env.add_filter("pluralize", pluralize);
+    env.add_filter("singularize", singularize);
+    env.add_filter("rust_identifier", rust_identifier);
+    env.add_filter("python_identifier", python_identifier);
+    env.add_filter("java_identifier", java_identifier);
+}
+
+/// Splits text into words at separators and case changes. An acronym stays a
+/// single word, so `HTTPServer` is `HTTP` and `Server`, and digits belong to
+/// the word before them, so `v2Beta` is `v2` and `Beta`.
+fn words(value: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let chars: Vec<char> = value.chars().collect();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        if let Some(previous) = current.chars().last() {
+            let next_is_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            let boundary = c.is_uppercase()
+                && (previous.is_lowercase()
+                    || previous.is_numeric()
+                    || (previous.is_uppercase() && next_is_lower));
+            if boundary {
+                words.push(std::mem::take(&mut current));
+            }
+        }
+        current.push(c);
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+fn snake_case(value: &str) -> String {
+    words(value)
+        .iter()
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+fn kebab_case(value: &str) -> String {
+    words(value)
+        .iter()
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn pascal_case(value: &str) -> String {
+    words(value).iter().map(|w| capitalize(w)).collect()
+}
+
+fn camel_case(value: &str) -> String {
+    words(value)
+        .iter()
+        .enumerate()
+        .map(|(i, w)| {
+            if i == 0 {
+                w.to_lowercase()
+            } else {
+                capitalize(w)
+            }
+        })
+        .collect()
+}
+
+fn screaming_snake_case(value: &str) -> String {
+    words(value)
+        .iter()
+        .map(|w| w.to_uppercase())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Lowercase words joined by hyphens. Unlike `kebab_case` it doesn't split
+/// on case changes, so `iPhone Case` is `iphone-case`. Non-ASCII letters are
+/// kept as they are.
+fn slugify(value: &str) -> String {
+    value
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+// Nouns that are the same in the singular and the plural
+const UNCOUNTABLE: &[&str] = &[
+    "data",
+    "equipment",
+    "fish",
+    "information",
+    "metadata",
+    "news",
+    "series",
+    "sheep",
+    "species",
+];
+
+// Words the suffix rules below get wrong in either direction. Only these
+// words take the `f`/`fe` to `ves` and `ie` to `ies` forms
+const IRREGULAR: &[(&str, &str)] = &[
+    ("child", "children"),
+    ("foot", "feet"),
+    ("goose", "geese"),
+    ("index", "indices"),
+    ("man", "men"),
+    ("mouse", "mice"),
+    ("person", "people"),
+    ("tooth", "teeth"),
+    ("woman", "women"),
+    // f and fe to ves
+    ("calf", "calves"),
+    ("elf", "elves"),
+    ("half", "halves"),
+    ("knife", "knives"),
+    ("leaf", "leaves"),
+    ("life", "lives"),
+    ("loaf", "loaves"),
+    ("self", "selves"),
+    ("shelf", "shelves"),
+    ("thief", "thieves"),
+    ("wife", "wives"),
+    ("wolf", "wolves"),
+    // ie to ies, which would otherwise become y
+    ("cookie", "cookies"),
+    ("movie", "movies"),
+    ("pie", "pies"),
+    ("rookie", "rookies"),
+    ("tie", "ties"),
+    ("zombie", "zombies"),
+    // o to oes
+    ("echo", "echoes"),
+    ("hero", "heroes"),
+    ("potato", "potatoes"),
+    ("tomato", "tomatoes"),
+    ("veto", "vetoes"),
+    // Singulars ending in s, z or e next to an es plural
+    ("alias", "aliases"),
+    ("analysis", "analyses"),
+    ("bus", "buses"),
+    ("cache", "caches"),
+    ("crisis", "crises"),
+    ("niche", "niches"),
+    ("quiz", "quizzes"),
+    ("status", "statuses"),
+    ("virus", "viruses"),
+];
+
+// Endings that take es in the plural
+const SIBILANTS: &[&str] = &["s", "x", "z", "ch", "sh"];
+
+/// English plural of the last word, keeping the case of the original.
+fn pluralize(value: &str) -> String {
+    let rule = |word: &str| {
+        if let Some((_, plural)) = IRREGULAR.iter().find(|(s, _)| *s == word) {
+            return (*plural).to_string();
+        }
+        if let Some(stem) = word.strip_suffix('y') {
+            if stem.ends_with(|c: char| !"aeiou".contains(c)) {
+                return format!("{stem}ies");
+            }
+        }
+        if SIBILANTS.iter().any(|s| word.ends_with(s)) {
+            return format!("{word}es");
+        }
+        format!("{word}s")
+    };
+    inflect(value, rule, |acronym| format!("{acronym}s"))
+}
+
+/// English singular of the last word, keeping the case of the original.
+fn singularize(value: &str) -> String {
+    let rule = |word: &str| {
+        if let Some((singular, _)) = IRREGULAR.iter().find(|(_, p)| *p == word) {
+            return (*singular).to_string();
+        }
+        if let Some(stem) = word.strip_suffix("ies") {
+            return format!("{stem}y");
+        }
+        // Only the es of a sibilant is part of the plural, so `boxes` is
+        // `box` but `databases`, `sizes` and `caches` keep their e
+        if let Some(stem) = word.strip_suffix("es") {
+            if ["ss", "x", "zz", "ch", "sh"]
+                .iter()
+                .any(|s| stem.ends_with(s))
+            {
+                return stem.to_string();
+            }
+        }
+        match word.strip_suffix('s') {
+            // Singular nouns like `class`, `status` and `analysis` end in s too
+            Some(stem) if !["ss", "us", "is"].iter().any(|s| word.ends_with(s)) => stem.to_string(),
+            _ => word.to_string(),
+        }
+    };
+    inflect(value, rule, str::to_string)
+}
+
+// Applies an inflection to the last word of `value`, leaving the words before
+// it as they are and matching its case. An acronym like `URL` keeps its case
+// and is inflected by `acronym`, without the plural `s` that `URLs` ends in
+fn inflect<F, A>(value: &str, rule: F, acronym: A) -> String
+where
+    F: Fn(&str) -> String,
+    A: Fn(&str) -> String,
+{
+    let Some(word) = words(value).pop() else {
+        return value.to_string();
+    };
+    // Words keep the characters of `value`, and only separators follow the
+    // last one
+    let start = value.rfind(&word).unwrap_or_default();
+    let (prefix, rest) = value.split_at(start);
+    let suffix = &rest[word.len()..];
+    let lower = word.to_lowercase();
+    if UNCOUNTABLE.contains(&lower.as_str()) {
+        return value.to_string();
+    }
+
+    let stem = word.strip_suffix('s').unwrap_or(&word);
+    let screaming =
+        value.contains(|c: char| !c.is_alphanumeric()) && !value.chars().any(char::is_lowercase);
+    let inflected = if screaming {
+        rule(&lower).to_uppercase()
+    } else if stem.chars().count() > 1 && stem.chars().all(char::is_uppercase) {
+        acronym(stem)
+    } else if word.starts_with(char::is_uppercase) {
+        capitalize(&rule(&lower))
+    } else {
+        rule(&lower)
+    };
+    format!("{prefix}{inflected}{suffix}")
+}
+
+// Replaces anything that can't be in an identifier and makes sure it doesn't
+// start with a digit
+fn identifier(value: &str) -> String {
+    let mut identifier: String = value
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if identifier.is_empty() || identifier.starts_with(|c: char| c.is_numeric()) {
+        identifier.insert(0, '_');
+    }
+    identifier
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+const PYTHON_KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield",
+];
+
+const JAVA_KEYWORDS: &[&str] = &[
+    "abstract",
+    "assert",
+    "boolean",
+    "break",
+    "byte",
+    "case",
+    "catch",
+    "char",
+    "class",
+    "const",
+    "continue",
+    "default",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extends",
+    "false",
+    "final",
+    "finally",
+    "float",
+    "for",
+    "goto",
+    "if",
+    "implements",
+    "import",
+    "instanceof",
+    "int",
+    "interface",
+    "long",
+    "native",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "short",
+    "static",
+    "strictfp",
+    "super",
+    "switch",
+    "synchronized",
+    "this",
+    "throw",
+    "throws",
+    "transient",
+    "true",
+    "try",
+    "var",
+    "void",
+    "volatile",
+    "while",
+    "_",
+];
+
+/// A valid Rust identifier. Keywords become raw identifiers, except the few
+/// that can't be raw, which get a trailing underscore.
+fn rust_identifier(value: &str) -> String {
+    let identifier = identifier(value);
+    match identifier.as_str() {
+        "crate" | "self" | "Self" | "super" | "_" => format!("{identifier}_"),
+        keyword if RUST_KEYWORDS.contains(&keyword) => format!("r#{identifier}"),
+        _ => identifier,
+    }
+}
+
+/// A valid Python identifier. Keywords get a trailing underscore, as PEP 8
+/// suggests.
+fn python_identifier(value: &str) -> String {
+    let identifier = identifier(value);
+    if PYTHON_KEYWORDS.contains(&identifier.as_str()) {
+        format!("{identifier}_")
+    } else {
+        identifier
+    }
+}
+
+/// A valid Java identifier. Keywords and literals get a trailing underscore.
+fn java_identifier(value: &str) -> String {
+    let identifier = identifier(value);
+    if JAVA_KEYWORDS.contains(&identifier.as_str()) {
+        format!("{identifier}_")
+    } else {
+        identifier
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright (c) 2025 David Stanek <dstanek@dstanek.com>
+
+use minijinja::value::{Kwargs, Value, ValueKind};
+use minijinja::Environment;
+use serde::Serialize;
+use serde_json::ser::{PrettyFormatter, Serializer};
+
+use crate::new_err;
+
+/// Registers the `to_toml`, `to_yaml` and `to_json` filters and the
+/// `from_toml`, `from_yaml` and `from_json` parsers. The parsers work as both
+/// functions and filters.
+///
+/// Serialized output has no trailing newline so it can be used inline, like
+/// `version = {{ version | to_toml }}`.
+pub(crate) fn register(env: &mut Environment) {
+    env.add_filter("to_toml", to_toml);
+    env.add_filter("to_yaml", to_yaml);
+    env.add_filter("to_json", to_json);
+
+    env.add_function("from_toml", from_toml);
+    env.add_function("from_yaml", from_yaml);
+    env.add_function("from_json", from_json);
+    env.add_filter("from_toml", from_toml);
+    env.add_filter("from_yaml", from_yaml);
+    env.add_filter("from_json", from_json);
+}
+
+/// A map becomes a TOML document; anything else becomes an inline TOML
+/// value, like `"text"` or `[1, 2]`.
+fn to_toml(value: &Value) -> Result<Value, minijinja::Error> {
+    let toml = if value.kind() == ValueKind::Map {
+        toml::to_string(value)
+    } else {
+        toml::Value::try_from(value).map(|v| v.to_string())
+    }
+    .map_err(|e| new_err(&format!("to_toml: {e}")))?;
+    Ok(Value::from_safe_string(
+        toml.trim_end_matches('\n').to_string(),
+    ))
+}
+
+// Plain scalars that YAML 1.1 readers, like PyYAML and most GitHub Actions
+// tooling, take for booleans or null. serde_norway writes YAML 1.2, where
+// these are strings, so it leaves them unquoted
+const YAML_1_1_KEYWORDS: &[&str] = &[
+    "y", "Y", "yes", "Yes", "YES", "n", "N", "no", "No", "NO", "on", "On", "ON", "off", "Off",
+    "OFF", "true", "True", "TRUE", "false", "False", "FALSE", "null", "Null", "NULL", "~",
+];
+
+/// YAML that YAML 1.1 readers see the same way, so strings like `on` and
+/// `n` are always quoted.
+fn to_yaml(value: &Value) -> Result<Value, minijinja::Error> {
+    let error = |e: serde_norway::Error| new_err(&format!("to_yaml: {e}"));
+    let yaml = serde_norway::to_value(value).map_err(error)?;
+
+    // serde_norway can't be asked to quote a string, but it does quote digits
+    // with a leading zero. Keywords are swapped for those and swapped back in
+    // the output, using more nines than any run of digits it has otherwise
+    let plain = serde_norway::to_string(&yaml).map_err(error)?;
+    let longest = plain
+        .split(|c: char| !c.is_ascii_digit())
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let marker = format!("0{}", "9".repeat(longest + 1));
+    let mut keywords = Vec::new();
+    let yaml = swap_keywords(yaml, &mut |keyword| {
+        keywords.push(keyword);
+        format!("{marker}{}", keywords.len())
+    });
+
+    let mut yaml = serde_norway::to_string(&yaml).map_err(error)?;
+    for (i, keyword) in keywords.iter().enumerate() {
+        let swapped = format!("'{marker}{}'", i + 1);
+        yaml = yaml.replacen(&swapped, &format!("'{keyword}'"), 1);
+    }
+    Ok(Value::from_safe_string(
+        yaml.trim_end_matches('\n').to_string(),
+    ))
+}
+
+fn swap_keywords<F>(value: serde_norway::Value, swap: &mut F) -> serde_norway::Value
+where
+    F: FnMut(String) -> String,
+{
+    use serde_norway::Value as Yaml;
+    match value {
+        Yaml::String(s) if YAML_1_1_KEYWORDS.contains(&s.as_str()) => Yaml::String(swap(s)),
+        Yaml::Sequence(items) => {
+            Yaml::Sequence(items.into_iter().map(|v| swap_keywords(v, swap)).collect())
+        }
+        Yaml::Mapping(map) => Yaml::Mapping(
+            map.into_iter()
+                .map(|(k, v)| (swap_keywords(k, swap), swap_keywords(v, swap)))
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+/// Compact JSON, or pretty-printed with `indent` spaces per level.
+fn to_json(value: &Value, kwargs: Kwargs) -> Result<Value, minijinja::Error> {
+    let indent: Option<usize> = kwargs.get("indent")?;
+    kwargs.assert_all_used()?;
+
+    let json = match indent {
+        None => serde_json::to_vec(value),
+        Some(indent) => {
+            let spaces = " ".repeat(indent);
+            let mut json = Vec::new();
+            let mut serializer = Serializer::with_formatter(
+                &mut json,
+                PrettyFormatter::with_indent(spaces.as_bytes()),
+            );
+            value.serialize(&mut serializer).map(|()| json)
+        }
+    }
+    .map_err(|e| new_err(&format!("to_json: {e}")))?;
+    Ok(Value::from_safe_string(String::from_utf8(json).unwrap()))
+}
+
+fn from_toml(text: &str) -> Result<Value, minijinja::Error> {
+    let value: toml::Table =
+        toml::from_str(text).map_err(|e| new_err(&format!("from_toml: {e}")))?;
+    Ok(Value::from_serialize(value))
+}
+
+fn from_yaml(text: &str) -> Result<Value, minijinja::Error> {
+    let value: serde_norway::Value =
+        serde_norway::from_str(text).map_err(|e| new_err(&format!("from_yaml: {e}")))?;
+    Ok(Value::from_serialize(value))
+}
+
+fn from_json(text: &str) -> Result<Value, minijinja::Error> {
+    let value: serde_json::Value =
+        serde_json::from_str(text).map_err(|e| new_err(&format!("from_json: {e}")))?;
+    Ok(Value::from_serialize(value))
+}
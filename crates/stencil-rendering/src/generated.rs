@@ -0,0 +1,72 @@
+// Copyright (c) 2025 David Stanek <dstanek@dstanek.com>
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use minijinja::value::Kwargs;
+use rand::distr::{Alphanumeric, SampleString};
+use rand::Rng;
+
+use crate::new_err;
+
+/// Values that are generated the first time a template asks for them and
+/// reused on every later render, so that syncing a project is idempotent.
+#[derive(Debug, Clone, Default)]
+pub struct GeneratedValues(Arc<Mutex<BTreeMap<String, String>>>);
+
+impl GeneratedValues {
+    pub fn new(values: BTreeMap<String, String>) -> Self {
+        GeneratedValues(Arc::new(Mutex::new(values)))
+    }
+
+    pub fn values(&self) -> BTreeMap<String, String> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Returns the stored value for `name`, generating one of the given kind
+    /// if there isn't one yet. Dates and timestamps are taken from `now`.
+    pub fn get_or_generate(
+        &self,
+        name: &str,
+        kind: &str,
+        kwargs: &Kwargs,
+        now: DateTime<Utc>,
+    ) -> Result<String, minijinja::Error> {
+        let mut values = self.0.lock().unwrap();
+        if let Some(value) = values.get(name) {
+            return Ok(value.clone());
+        }
+        let value = generate(kind, kwargs, now)?;
+        values.insert(name.to_string(), value.clone());
+        Ok(value)
+    }
+}
+
+fn generate(kind: &str, kwargs: &Kwargs, now: DateTime<Utc>) -> Result<String, minijinja::Error> {
+    let value = match kind {
+        "uuid" => uuid::Uuid::new_v4().to_string(),
+        "secret" => {
+            let length: Option<usize> = kwargs.get("length")?;
+            Alphanumeric.sample_string(&mut rand::rng(), length.unwrap_or(32))
+        }
+        "int" => {
+            let min: Option<i64> = kwargs.get("min")?;
+            let max: Option<i64> = kwargs.get("max")?;
+            let (min, max) = (min.unwrap_or(0), max.unwrap_or(i64::from(u16::MAX)));
+            if min > max {
+                return Err(new_err("generated: min must not be greater than max"));
+            }
+            rand::rng().random_range(min..=max).to_string()
+        }
+        "date" => now.format("%Y-%m-%d").to_string(),
+        "timestamp" => now.to_rfc3339_opts(SecondsFormat::Secs, true),
+        _ => {
+            return Err(new_err(&format!(
+                "generated: unknown kind '{kind}', expected one of uuid, secret, int, date or timestamp"
+            )))
+        }
+    };
+    kwargs.assert_all_used()?;
+    Ok(value)
+}
@@ -1,14 +1,26 @@
 // Copyright (c) 2025 David Stanek <dstanek@dstanek.com>
 
 use regex::Regex;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 
-use minijinja::value::{Kwargs, Rest, Value};
-use minijinja::{Environment, State};
+use chrono::{DateTime, Utc};
+use minijinja::syntax::SyntaxConfig;
+use minijinja::value::{Kwargs, Rest, Value, ValueKind};
+use minijinja::{Environment, State, UndefinedBehavior};
+use serde::Serialize;
 
+mod context;
 mod extract;
+mod filters;
+mod formats;
+mod generated;
+mod syntax;
 mod template_var;
 
+use generated::GeneratedValues;
+pub use syntax::Syntax;
 pub use template_var::TemplateVar;
 
 #[derive(Debug, thiserror::Error)]
@@ -79,14 +91,32 @@ fn user_content_function(
     Ok(format!("{}{}{}", begin_marker, content, end_marker))
 }
 
+// Errors from an included template keep it as their source, so that it can
+// be located
+fn template_error(e: minijinja::Error) -> RenderError {
+    if std::error::Error::source(&e).is_some() {
+        return RenderError::TemplateError(e);
+    }
+    let detail = e.detail().unwrap_or("").to_string();
+    RenderError::TemplateError(e.with_source(new_err(&detail)))
+}
+
 fn new_err(msg: &str) -> minijinja::Error {
     minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, msg.to_string())
 }
 
-/// Transforms `{% include 'file.j2' indent content %}` into `{% filter indent(n, true) %}{% include 'file.j2' %}{% endfilter %}`
-fn transform_indent_content(src: &str) -> String {
-    let pattern =
-        Regex::new(r"(?m)^(?P<indent>\s*)\{\{-?\s*user_content\s*(?P<expr>.+?)\s*-?\}\}").unwrap();
+/// Transforms `{{ user_content(...) }}` on a line of its own into
+/// `{% filter indent(n, true) %}{{ user_content(...) }}{% endfilter +%}`, using
+/// the delimiters of `syntax`.
+fn transform_indent_content(src: &str, syntax: &SyntaxConfig) -> String {
+    let (block_start, block_end) = syntax.block_delimiters();
+    let (variable_start, variable_end) = syntax.variable_delimiters();
+    let pattern = Regex::new(&format!(
+        r"(?m)^(?P<indent>[ \t]*){}-?\s*user_content\s*(?P<expr>.+?)\s*-?{}",
+        regex::escape(variable_start),
+        regex::escape(variable_end),
+    ))
+    .unwrap();
 
     let result = pattern.replace_all(src, |caps: &regex::Captures| {
         let indent_str = &caps["indent"];
@@ -94,67 +124,256 @@ fn transform_indent_content(src: &str) -> String {
         let indent_len = indent_str.chars().count();
 
         format!(
-            "{{% filter indent({}, true) %}}{{{{ user_content{} }}}}{{% endfilter %}}",
-            indent_len, include_expr,
+            "{block_start} filter indent({indent_len}, true) {block_end}\
+             {variable_start} user_content{include_expr} {variable_end}\
+             {block_start} endfilter +{block_end}",
         )
     });
 
     result.into_owned()
 }
 
+/// Renders the templates of a single stencil. Everything rendered by the same
+/// renderer shares its variables and generated values, and the Jinja
+/// environment it builds on first use, so it is configured before rendering.
+#[derive(Debug)]
+pub struct Renderer {
+    vars: HashMap<String, TemplateVar>,
+    generated: GeneratedValues,
+    templates: Arc<BTreeMap<String, String>>,
+    globals: BTreeMap<String, Value>,
+    environment: Arc<BTreeMap<String, Option<String>>>,
+    now: DateTime<Utc>,
+    dest: Option<Arc<PathBuf>>,
+    strict: bool,
+    syntax: SyntaxConfig,
+    trim_blocks: bool,
+    lstrip_blocks: bool,
+    env: OnceLock<Environment<'static>>,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Renderer::new(HashMap::new())
+    }
+}
+
+impl Renderer {
+    pub fn new(vars: HashMap<String, TemplateVar>) -> Self {
+        Renderer {
+            vars,
+            generated: GeneratedValues::default(),
+            templates: Arc::default(),
+            globals: BTreeMap::new(),
+            environment: Arc::default(),
+            now: Utc::now(),
+            dest: None,
+            strict: false,
+            syntax: SyntaxConfig::default(),
+            trim_blocks: false,
+            lstrip_blocks: false,
+            env: OnceLock::new(),
+        }
+    }
+
+    /// Adds a global, like an object with nested fields, that plain template
+    /// variables can't express.
+    pub fn with_global<V: Serialize>(mut self, name: &str, value: V) -> Self {
+        self.globals
+            .insert(name.to_string(), Value::from_serialize(value));
+        self
+    }
+
+    /// The environment variables `env(...)` may read, with `None` for the
+    /// ones that aren't set. Reading any other variable is an error.
+    pub fn with_environment(mut self, environment: BTreeMap<String, Option<String>>) -> Self {
+        self.environment = Arc::new(environment);
+        self
+    }
+
+    /// The time `now(...)` returns, instead of the time the renderer was
+    /// created.
+    pub fn with_now(mut self, now: DateTime<Utc>) -> Self {
+        self.now = now;
+        self
+    }
+
+    /// Makes templates available by name to `include`, `import` and
+    /// `extends`.
+    pub fn with_templates(mut self, templates: BTreeMap<String, String>) -> Self {
+        self.templates = Arc::new(templates);
+        self
+    }
+
+    /// Makes printing, iterating or looking into an undefined variable an
+    /// error. Checking one with `{% if name %}` is still allowed.
+    pub fn with_strict_undefined(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// The syntax of the templates, which applies to paths as well as
+    /// contents.
+    pub fn with_syntax(mut self, syntax: &Syntax) -> Result<Self, RenderError> {
+        self.syntax = syntax.config()?;
+        self.trim_blocks = syntax.trim_blocks;
+        self.lstrip_blocks = syntax.lstrip_blocks;
+        Ok(self)
+    }
+
+    /// The destination that `dest_file(...)` and friends read from.
+    pub fn with_dest(mut self, dest: &Path) -> Self {
+        self.dest = Some(Arc::new(dest.to_path_buf()));
+        self
+    }
+
+    /// Seeds the values previously created by `generated(...)` calls.
+    pub fn with_generated(mut self, values: BTreeMap<String, String>) -> Self {
+        self.generated = GeneratedValues::new(values);
+        self
+    }
+
+    /// All generated values, including the ones created while rendering.
+    pub fn generated(&self) -> BTreeMap<String, String> {
+        self.generated.values()
+    }
+
+    /// The source a template made available with `with_templates` is
+    /// compiled from. Error locations point into this.
+    pub fn template(&self, name: &str) -> Option<String> {
+        self.templates.get(name).map(|src| self.compiled(src))
+    }
+
+    /// The source `render` compiles a template's content into. It has the
+    /// same lines as the content, but `user_content` lines are rewritten, so
+    /// error locations point into this rather than the content.
+    pub fn compiled(&self, content: &str) -> String {
+        transform_indent_content(content, &self.syntax)
+    }
+
+    pub fn render<T: Renderable>(&self, src: &T, dest: &T) -> Result<String, RenderError> {
+        // Each file has its own content blocks
+        let mut env = self.environment().clone();
+        let content_blocks = extract::extract_blocks(dest.content())
+            .map_err(|e| RenderError::BlockParseError(e.to_string()))?;
+
+        env.add_global("content_blocks", content_blocks);
+        env.add_function("user_content", user_content_function);
+
+        let compiled = self.compiled(src.content());
+        let tmpl = env.template_from_str(&compiled)?;
+        tmpl.render(context(&self.vars)).map_err(template_error)
+    }
+
+    pub fn render_str(&self, template: &str) -> Result<String, RenderError> {
+        let env = self.environment();
+        let tmpl = env.template_from_str(template)?;
+        tmpl.render(context(&self.vars)).map_err(template_error)
+    }
+
+    /// Evaluates a single Jinja expression, like `project_name | upper`. With
+    /// strict undefined, an expression that is undefined is an error.
+    pub fn evaluate(&self, expression: &str) -> Result<TemplateVar, RenderError> {
+        let value = self.evaluate_value(expression)?;
+        Ok(match value.as_i64() {
+            Some(i) if value.is_integer() => TemplateVar::Int(i),
+            _ if value.kind() == ValueKind::Bool => TemplateVar::Bool(value.is_true()),
+            _ => TemplateVar::String(value.to_string()),
+        })
+    }
+
+    /// Like `evaluate`, but keeps the value as Jinja sees it, so that its
+    /// truthiness is Jinja's too.
+    pub fn evaluate_value(&self, expression: &str) -> Result<Value, RenderError> {
+        let env = self.environment();
+        let expr = env.compile_expression(expression)?;
+        let value = expr.eval(context(&self.vars))?;
+        if self.strict && value.is_undefined() {
+            return Err(RenderError::TemplateError(minijinja::Error::new(
+                minijinja::ErrorKind::UndefinedError,
+                format!("'{expression}' is undefined"),
+            )));
+        }
+        Ok(value)
+    }
+
+    fn environment(&self) -> &Environment<'static> {
+        self.env.get_or_init(|| self.new_environment())
+    }
+
+    fn new_environment(&self) -> Environment<'static> {
+        let mut env = Environment::new();
+        env.set_keep_trailing_newline(true);
+        env.set_syntax(self.syntax.clone());
+        env.set_trim_blocks(self.trim_blocks);
+        env.set_lstrip_blocks(self.lstrip_blocks);
+        if self.strict {
+            env.set_undefined_behavior(UndefinedBehavior::SemiStrict);
+        }
+        filters::register(&mut env);
+        formats::register(&mut env);
+        context::register(&mut env, Arc::clone(&self.environment), self.now);
+        context::register_dest(&mut env, self.dest.clone());
+        for (name, value) in &self.globals {
+            env.add_global(name.clone(), value.clone());
+        }
+
+        // Included templates get the same treatment as the one being rendered
+        let templates = Arc::clone(&self.templates);
+        let syntax = self.syntax.clone();
+        env.set_loader(move |name| {
+            Ok(templates
+                .get(name)
+                .map(|src| transform_indent_content(src, &syntax)))
+        });
+
+        let generated = self.generated.clone();
+        let now = self.now;
+        env.add_function(
+            "generated",
+            move |name: &str, kind: &str, kwargs: Kwargs| {
+                generated.get_or_generate(name, kind, &kwargs, now)
+            },
+        );
+        env
+    }
+}
+
 pub fn render<T: Renderable>(
     src: &T,
     dest: &T,
     vars: &HashMap<String, TemplateVar>,
 ) -> Result<String, RenderError> {
-    let mut env = Environment::new();
-    let content_blocks = extract::extract_blocks(dest.content())
-        .map_err(|e| RenderError::BlockParseError(e.to_string()))?;
-
-    env.set_keep_trailing_newline(true);
-    env.add_global("content_blocks", content_blocks);
-    env.add_function("user_content", user_content_function);
-
-    let context = vars
-        .iter()
-        .map(|(k, v)| {
-            let v = match v {
-                TemplateVar::String(s) => Value::from(s),
-                TemplateVar::Int(i) => Value::from(*i),
-            };
-            (k.to_string(), v)
-        })
-        .collect::<HashMap<String, Value>>();
-    let transformed = transform_indent_content(src.content());
-    let tmpl = env.template_from_str(&transformed)?;
-    tmpl.render(context).map_err(|e| {
-        let detail = e.detail().unwrap_or("").to_string();
-        RenderError::TemplateError(e.with_source(new_err(&detail)))
-    })
+    Renderer::new(vars.clone()).render(src, dest)
 }
 
 pub fn render_str(
     template: &str,
     vars: &HashMap<String, TemplateVar>,
 ) -> Result<String, RenderError> {
-    let mut env = Environment::new();
-    env.set_keep_trailing_newline(true);
+    Renderer::new(vars.clone()).render_str(template)
+}
+
+/// Evaluates a single Jinja expression, like `project_name | upper`, against
+/// the variables.
+pub fn evaluate(
+    expression: &str,
+    vars: &HashMap<String, TemplateVar>,
+) -> Result<TemplateVar, RenderError> {
+    Renderer::new(vars.clone()).evaluate(expression)
+}
 
-    let context = vars
-        .iter()
+fn context(vars: &HashMap<String, TemplateVar>) -> HashMap<String, Value> {
+    vars.iter()
         .map(|(k, v)| {
             let v = match v {
                 TemplateVar::String(s) => Value::from(s),
                 TemplateVar::Int(i) => Value::from(*i),
+                TemplateVar::Bool(b) => Value::from(*b),
             };
             (k.to_string(), v)
         })
-        .collect::<HashMap<String, Value>>();
-    let tmpl = env.template_from_str(template)?;
-    tmpl.render(context).map_err(|e| {
-        let detail = e.detail().unwrap_or("").to_string();
-        RenderError::TemplateError(e.with_source(new_err(&detail)))
-    })
+        .collect()
 }
 
 fn get_value(args: &[Value], kwargs: &Kwargs, index: usize, key: &str) -> Option<String> {
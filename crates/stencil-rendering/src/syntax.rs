@@ -0,0 +1,60 @@
+// Copyright (c) 2025 David Stanek <dstanek@dstanek.com>
+
+use minijinja::syntax::SyntaxConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::new_err;
+
+/// The Jinja syntax of a stencil's templates. Changing the delimiters lets
+/// templates produce files, like GitHub Actions workflows, that use `{{ }}`
+/// themselves. It is read from a stencil's `[syntax]` table.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Syntax {
+    /// Start and end delimiters, like `["<%", "%>"]`.
+    pub block: (String, String),
+    pub variable: (String, String),
+    pub comment: (String, String),
+    pub line_statement_prefix: Option<String>,
+    pub line_comment_prefix: Option<String>,
+    /// Removes the first newline after a block tag.
+    pub trim_blocks: bool,
+    /// Removes the spaces and tabs before a block tag at the start of a line.
+    pub lstrip_blocks: bool,
+}
+
+// Jinja's own syntax
+impl Default for Syntax {
+    fn default() -> Self {
+        let config = SyntaxConfig::default();
+        let pair = |(start, end): (&str, &str)| (start.to_string(), end.to_string());
+        Syntax {
+            block: pair(config.block_delimiters()),
+            variable: pair(config.variable_delimiters()),
+            comment: pair(config.comment_delimiters()),
+            line_statement_prefix: config.line_statement_prefix().map(str::to_string),
+            line_comment_prefix: config.line_comment_prefix().map(str::to_string),
+            trim_blocks: false,
+            lstrip_blocks: false,
+        }
+    }
+}
+
+impl Syntax {
+    pub(crate) fn config(&self) -> Result<SyntaxConfig, minijinja::Error> {
+        let mut builder = SyntaxConfig::builder();
+        builder
+            .block_delimiters(self.block.0.clone(), self.block.1.clone())
+            .variable_delimiters(self.variable.0.clone(), self.variable.1.clone())
+            .comment_delimiters(self.comment.0.clone(), self.comment.1.clone());
+        if let Some(prefix) = &self.line_statement_prefix {
+            builder.line_statement_prefix(prefix.clone());
+        }
+        if let Some(prefix) = &self.line_comment_prefix {
+            builder.line_comment_prefix(prefix.clone());
+        }
+        builder.build().map_err(|_| {
+            new_err("invalid syntax: start delimiters and prefixes must be non-empty and distinct")
+        })
+    }
+}
@@ -5,6 +5,7 @@
 pub enum TemplateVar {
     String(String),
     Int(i64),
+    Bool(bool),
 }
 
 impl From<&str> for TemplateVar {
@@ -30,3 +31,9 @@ impl From<i32> for TemplateVar {
         TemplateVar::Int(i as i64)
     }
 }
+
+impl From<bool> for TemplateVar {
+    fn from(b: bool) -> Self {
+        TemplateVar::Bool(b)
+    }
+}
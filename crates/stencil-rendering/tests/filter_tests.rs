@@ -0,0 +1,189 @@
+// Copyright (c) 2025 David Stanek <dstanek@dstanek.com>
+
+use std::collections::HashMap;
+
+use stencil_rendering::render_str;
+
+fn filter(name: &str, value: &str) -> String {
+    let vars = HashMap::from([("value".to_string(), value.into())]);
+    render_str(&format!("{{{{ value | {name} }}}}"), &vars).unwrap()
+}
+
+#[test]
+fn test_case_filters() {
+    let cases = [
+        // input, snake, kebab, pascal, camel, screaming
+        (
+            "my project",
+            "my_project",
+            "my-project",
+            "MyProject",
+            "myProject",
+            "MY_PROJECT",
+        ),
+        (
+            "myProject",
+            "my_project",
+            "my-project",
+            "MyProject",
+            "myProject",
+            "MY_PROJECT",
+        ),
+        (
+            "HTTPServer",
+            "http_server",
+            "http-server",
+            "HttpServer",
+            "httpServer",
+            "HTTP_SERVER",
+        ),
+        (
+            "parseJSONFile",
+            "parse_json_file",
+            "parse-json-file",
+            "ParseJsonFile",
+            "parseJsonFile",
+            "PARSE_JSON_FILE",
+        ),
+        (
+            "v2Beta", "v2_beta", "v2-beta", "V2Beta", "v2Beta", "V2_BETA",
+        ),
+        (
+            "python3_version",
+            "python3_version",
+            "python3-version",
+            "Python3Version",
+            "python3Version",
+            "PYTHON3_VERSION",
+        ),
+        (
+            "--Already_snake--",
+            "already_snake",
+            "already-snake",
+            "AlreadySnake",
+            "alreadySnake",
+            "ALREADY_SNAKE",
+        ),
+        (
+            "Crème Brûlée",
+            "crème_brûlée",
+            "crème-brûlée",
+            "CrèmeBrûlée",
+            "crèmeBrûlée",
+            "CRÈME_BRÛLÉE",
+        ),
+        ("", "", "", "", "", ""),
+    ];
+    for (input, snake, kebab, pascal, camel, screaming) in cases {
+        assert_eq!(filter("snake_case", input), snake, "snake_case({input:?})");
+        assert_eq!(filter("kebab_case", input), kebab, "kebab_case({input:?})");
+        assert_eq!(
+            filter("pascal_case", input),
+            pascal,
+            "pascal_case({input:?})"
+        );
+        assert_eq!(filter("camel_case", input), camel, "camel_case({input:?})");
+        assert_eq!(
+            filter("screaming_snake_case", input),
+            screaming,
+            "screaming_snake_case({input:?})"
+        );
+    }
+}
+
+#[test]
+fn test_slugify() {
+    assert_eq!(filter("slugify", "Hello, World!"), "hello-world");
+    assert_eq!(filter("slugify", "  iPhone   Case 2 "), "iphone-case-2");
+    assert_eq!(filter("slugify", "Crème Brûlée"), "crème-brûlée");
+    assert_eq!(filter("slugify", "---"), "");
+}
+
+// Singular and plural pairs that both filters must agree on
+const INFLECTIONS: &[(&str, &str)] = &[
+    ("user", "users"),
+    ("box", "boxes"),
+    ("branch", "branches"),
+    ("category", "categories"),
+    ("key", "keys"),
+    ("knife", "knives"),
+    ("shelf", "shelves"),
+    ("person", "people"),
+    ("child", "children"),
+    ("sheep", "sheep"),
+    ("database", "databases"),
+    ("response", "responses"),
+    ("case", "cases"),
+    ("license", "licenses"),
+    ("house", "houses"),
+    ("archive", "archives"),
+    ("drive", "drives"),
+    ("valve", "valves"),
+    ("movie", "movies"),
+    ("cookie", "cookies"),
+    ("cafe", "cafes"),
+    ("safe", "safes"),
+    ("golf", "golfs"),
+    ("quiz", "quizzes"),
+    ("hero", "heroes"),
+    ("size", "sizes"),
+    ("cache", "caches"),
+    ("status", "statuses"),
+    ("Address", "Addresses"),
+    ("URL", "URLs"),
+    ("HTTPServer", "HTTPServers"),
+    ("APIKey", "APIKeys"),
+    ("API_KEY", "API_KEYS"),
+    ("user_account", "user_accounts"),
+    ("UserAccount", "UserAccounts"),
+];
+
+#[test]
+fn test_inflection_filters() {
+    for (singular, plural) in INFLECTIONS {
+        assert_eq!(
+            filter("pluralize", singular),
+            *plural,
+            "pluralize({singular:?})"
+        );
+        assert_eq!(
+            filter("singularize", plural),
+            *singular,
+            "singularize({plural:?})"
+        );
+    }
+    assert_eq!(filter("singularize", "status"), "status");
+    assert_eq!(filter("pluralize", ""), "");
+}
+
+#[test]
+fn test_inflection_round_trip() {
+    for (singular, plural) in INFLECTIONS {
+        let vars = HashMap::from([("value".to_string(), (*singular).into())]);
+        let observed = render_str("{{ value | pluralize | singularize }}", &vars).unwrap();
+        assert_eq!(observed, *singular, "round trip of {singular:?}");
+
+        let vars = HashMap::from([("value".to_string(), (*plural).into())]);
+        let observed = render_str("{{ value | singularize | pluralize }}", &vars).unwrap();
+        assert_eq!(observed, *plural, "round trip of {plural:?}");
+    }
+}
+
+#[test]
+fn test_identifier_filters() {
+    assert_eq!(filter("rust_identifier", "my-crate"), "my_crate");
+    assert_eq!(filter("rust_identifier", "type"), "r#type");
+    assert_eq!(filter("rust_identifier", "self"), "self_");
+    assert_eq!(filter("rust_identifier", "3d-engine"), "_3d_engine");
+
+    assert_eq!(filter("python_identifier", "class"), "class_");
+    assert_eq!(filter("python_identifier", "my.package"), "my_package");
+    assert_eq!(filter("python_identifier", "None"), "None_");
+    assert_eq!(filter("python_identifier", "naïve"), "naïve");
+
+    assert_eq!(filter("java_identifier", "package"), "package_");
+    assert_eq!(filter("java_identifier", "my app"), "my_app");
+    assert_eq!(filter("java_identifier", ""), "__");
+
+    assert_eq!(filter("snake_case | rust_identifier", "Type"), "r#type");
+}
@@ -0,0 +1,116 @@
+// Copyright (c) 2025 David Stanek <dstanek@dstanek.com>
+
+use std::collections::HashMap;
+
+use stencil_rendering::{render_str, TemplateVar};
+
+fn render(template: &str) -> String {
+    let vars = HashMap::from([
+        (
+            "name".to_string(),
+            TemplateVar::from("my \"quoted\" project"),
+        ),
+        ("port".to_string(), TemplateVar::Int(8080)),
+    ]);
+    render_str(template, &vars).unwrap()
+}
+
+const SERVICE: &str = r#"{"name": "web", "ports": [80, 443], "env": {"DEBUG": false}}"#;
+
+#[test]
+fn test_to_toml() {
+    assert_eq!(
+        render("name = {{ name | to_toml }}"),
+        r#"name = 'my "quoted" project'"#
+    );
+    let observed = render(r#"name = {{ "it's \"both\"\n" | to_toml }}"#);
+    let parsed: toml::Table = toml::from_str(&observed).unwrap();
+    assert_eq!(parsed["name"].as_str(), Some("it's \"both\"\n"));
+    assert_eq!(
+        render("ports = {{ [80, 443] | to_toml }}"),
+        "ports = [80, 443]"
+    );
+    assert_eq!(
+        render(&format!("{{{{ '{SERVICE}' | from_json | to_toml }}}}")),
+        "name = \"web\"\nports = [80, 443]\n\n[env]\nDEBUG = false"
+    );
+    assert!(render_str("{{ none | to_toml }}", &HashMap::new()).is_err());
+}
+
+#[test]
+fn test_to_yaml() {
+    assert_eq!(
+        render("name: {{ name | to_yaml }}"),
+        r#"name: my "quoted" project"#
+    );
+    assert_eq!(render("debug: {{ 'true' | to_yaml }}"), "debug: 'true'");
+    assert_eq!(render("port: {{ '8080' | to_yaml }}"), "port: '8080'");
+
+    // Strings that YAML 1.1 readers take for booleans or null are quoted
+    assert_eq!(render("{{ {'on': 'push'} | to_yaml }}"), "'on': push");
+    assert_eq!(render("{{ {'y': 'n'} | to_yaml }}"), "'y': 'n'");
+    assert_eq!(render("v: {{ 'off' | to_yaml }}"), "v: 'off'");
+    assert_eq!(
+        render("{{ ['yes', 'No', '~', 'Null', 'on 1', 90999] | to_yaml }}"),
+        "- 'yes'\n- 'No'\n- '~'\n- 'Null'\n- on 1\n- 90999"
+    );
+    assert_eq!(
+        render(&format!("{{{{ from_json('{SERVICE}') | to_yaml }}}}")),
+        "name: web\nports:\n- 80\n- 443\nenv:\n  DEBUG: false"
+    );
+}
+
+#[test]
+fn test_to_yaml_round_trip() {
+    let values = [
+        "yes",
+        "no",
+        "on",
+        "off",
+        "*anchor",
+        "&anchor",
+        "key: value",
+        "http://example.com:8080",
+        "line one\nline two\n",
+        "trailing: colon:",
+    ];
+    for value in values {
+        let vars = HashMap::from([("value".to_string(), TemplateVar::from(value))]);
+        let yaml = render_str("value: {{ value | to_yaml }}\nnext: 1\n", &vars).unwrap();
+        let parsed: HashMap<String, serde_norway::Value> = serde_norway::from_str(&yaml).unwrap();
+        assert_eq!(parsed["value"].as_str(), Some(value), "{yaml:?}");
+        assert_eq!(parsed["next"].as_i64(), Some(1), "{yaml:?}");
+
+        let vars = HashMap::from([("yaml".to_string(), TemplateVar::from(yaml.as_str()))]);
+        let observed = render_str("{{ (yaml | from_yaml).value }}", &vars).unwrap();
+        assert_eq!(observed, value);
+    }
+}
+
+#[test]
+fn test_to_json() {
+    assert_eq!(render("{{ name | to_json }}"), r#""my \"quoted\" project""#);
+    assert_eq!(
+        render("{{ {'port': port, 'tags': ['a']} | to_json }}"),
+        r#"{"port":8080,"tags":["a"]}"#
+    );
+    assert_eq!(
+        render("{{ {'port': port, 'tags': ['a']} | to_json(indent=4) }}"),
+        "{\n    \"port\": 8080,\n    \"tags\": [\n        \"a\"\n    ]\n}"
+    );
+    assert!(render_str("{{ 1 | to_json(indnt=2) }}", &HashMap::new()).is_err());
+}
+
+#[test]
+fn test_from_formats() {
+    assert_eq!(
+        render("{{ (from_toml('[tool]\\nname = \"x\"')).tool.name }}"),
+        "x"
+    );
+    assert_eq!(render("{{ ('a: [1, 2]' | from_yaml).a | length }}"), "2");
+    assert_eq!(
+        render(&format!("{{{{ from_json('{SERVICE}').ports[1] }}}}")),
+        "443"
+    );
+    assert!(render_str("{{ from_json('{') }}", &HashMap::new()).is_err());
+}
@@ -1,13 +1,13 @@
 // Copyright (c) 2025 David Stanek <dstanek@dstanek.com>
 
 mod file;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use file::File;
 
 use textwrap::dedent;
 
-use stencil_rendering::{render, render_str, TemplateVar};
+use stencil_rendering::{evaluate, render, render_str, RenderError, Renderer, Syntax, TemplateVar};
 
 #[test]
 fn test_render_python() {
@@ -280,3 +280,254 @@ fn test_render_python_with_existing_content() {
     };
     assert_eq!(observed.trim(), expected.trim());
 }
+
+#[test]
+fn test_evaluate_expression() {
+    let vars = HashMap::from([
+        ("project_name".to_string(), TemplateVar::from("My-Project")),
+        ("size".to_string(), TemplateVar::Int(3)),
+    ]);
+
+    match evaluate("project_name | lower | replace('-', '_')", &vars).unwrap() {
+        TemplateVar::String(s) => assert_eq!(s, "my_project"),
+        observed => panic!("Expected a string, but got {:?}", observed),
+    }
+    match evaluate("size * 2", &vars).unwrap() {
+        TemplateVar::Int(i) => assert_eq!(i, 6),
+        observed => panic!("Expected an int, but got {:?}", observed),
+    }
+    assert!(evaluate("project_name |", &vars).is_err());
+
+    // Strict undefined applies to expressions as it does to templates
+    assert!(evaluate("projct_name", &vars).is_ok());
+    let strict = Renderer::new(vars).with_strict_undefined(true);
+    assert!(strict.evaluate("projct_name").is_err());
+    assert!(strict.evaluate("projct_name is defined").is_ok());
+}
+
+#[test]
+fn test_generated_values_are_reused() {
+    let renderer = Renderer::new(HashMap::new());
+    let first = renderer
+        .render_str("{{ generated('service_id', 'uuid') }}")
+        .unwrap();
+    let second = renderer
+        .render_str("{{ generated('service_id', 'uuid') }}")
+        .unwrap();
+    assert_eq!(first.len(), 36);
+    assert_eq!(first, second);
+    assert_eq!(renderer.generated().get("service_id"), Some(&first));
+
+    let port = renderer
+        .render_str("{{ generated('port_offset', 'int', min=10, max=20) }}")
+        .unwrap();
+    assert!((10..=20).contains(&port.parse::<i64>().unwrap()));
+
+    let stored = BTreeMap::from([("service_id".to_string(), "stored".to_string())]);
+    let renderer = Renderer::new(HashMap::new()).with_generated(stored);
+    let observed = renderer
+        .render_str("{{ generated('service_id', 'uuid') }}")
+        .unwrap();
+    assert_eq!(observed, "stored");
+
+    assert!(renderer.render_str("{{ generated('x', 'nope') }}").is_err());
+}
+
+#[test]
+fn test_render_with_templates() {
+    let templates = BTreeMap::from([
+        (
+            "_partials/header.jinja".to_string(),
+            "# {{ project_name }}\n".to_string(),
+        ),
+        (
+            "_partials/macros.jinja".to_string(),
+            "{% macro greet(name) %}Hello, {{ name }}!{% endmacro %}".to_string(),
+        ),
+        (
+            "_partials/base.jinja".to_string(),
+            "{% block body %}{% endblock %}\n-- {{ project_name }}\n".to_string(),
+        ),
+    ]);
+    let vars = HashMap::from([("project_name".to_string(), TemplateVar::from("my_project"))]);
+    let renderer = Renderer::new(vars).with_templates(templates);
+
+    let observed = renderer
+        .render_str(
+            "{% include '_partials/header.jinja' %}\
+             {% from '_partials/macros.jinja' import greet %}{{ greet('world') }}\n",
+        )
+        .unwrap();
+    assert_eq!(observed, "# my_project\nHello, world!\n");
+
+    let observed = renderer
+        .render_str("{% extends '_partials/base.jinja' %}{% block body %}body{% endblock %}")
+        .unwrap();
+    assert_eq!(observed, "body\n-- my_project\n");
+
+    assert!(renderer
+        .render_str("{% include '_partials/missing.jinja' %}")
+        .is_err());
+}
+
+#[test]
+fn test_render_user_content_in_included_template() {
+    let templates = BTreeMap::from([(
+        "_partials/main.jinja".to_string(),
+        "def main():\n    {{ user_content(\"main\", '#') }}\n".to_string(),
+    )]);
+    let renderer = Renderer::new(HashMap::new()).with_templates(templates);
+    let file = File::new("{% include '_partials/main.jinja' %}");
+
+    let observed = renderer.render(&file, &file).unwrap();
+    assert_eq!(
+        observed,
+        "def main():\n    \
+         # begin-user-content:main\n    \
+         # user content here\n    \
+         # end-user-content:main\n"
+    );
+}
+
+#[test]
+fn test_render_context() {
+    let environment = BTreeMap::from([
+        ("CI".to_string(), Some("true".to_string())),
+        ("REGISTRY".to_string(), None),
+    ]);
+    let metadata = BTreeMap::from([("source", "gh://acme/stencils"), ("version", "1.2.0")]);
+    let renderer = Renderer::new(HashMap::new())
+        .with_environment(environment)
+        .with_now(chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap())
+        .with_global("stencil", metadata);
+
+    let observed = renderer
+        .render_str(
+            "{{ env('CI') }} {{ env('REGISTRY', 'ghcr.io') }} {{ env('REGISTRY') is none }}\n\
+             {{ now() }} {{ now('%Y') }}\n\
+             {{ stencil.source }}@{{ stencil.version }}",
+        )
+        .unwrap();
+    assert_eq!(
+        observed,
+        "true ghcr.io true\n2023-11-14T22:13:20Z 2023\ngh://acme/stencils@1.2.0"
+    );
+
+    assert!(renderer.render_str("{{ env('HOME') }}").is_err());
+    assert!(renderer.render_str("{{ now('%Q') }}").is_err());
+
+    // Generated dates use the same clock as now()
+    let observed = renderer
+        .render_str("{{ generated('created', 'date') }} {{ generated('at', 'timestamp') }}")
+        .unwrap();
+    assert_eq!(observed, "2023-11-14 2023-11-14T22:13:20Z");
+}
+
+#[test]
+fn test_render_dest_files() {
+    let dest = std::env::temp_dir().join(format!("stencil-dest-{}", std::process::id()));
+    std::fs::create_dir_all(&dest).unwrap();
+    std::fs::write(
+        dest.join("Cargo.toml"),
+        "[package]\nname = \"app\"\nversion = \"1.7.2\"\n",
+    )
+    .unwrap();
+    std::fs::write(dest.join("package.json"), r#"{"version": "0.3.0"}"#).unwrap();
+    std::fs::write(dest.join("Dockerfile"), "FROM scratch\n").unwrap();
+    let renderer = Renderer::new(HashMap::new()).with_dest(&dest);
+
+    let observed = renderer
+        .render_str(
+            "{{ dest_toml('Cargo.toml').package.version }} \
+             {{ dest_json('package.json').version }} \
+             {{ dest_file('Dockerfile') | trim }} \
+             {{ dest_file('missing.txt') is none }} {{ dest_toml('missing.toml') is none }}",
+        )
+        .unwrap();
+    assert_eq!(observed, "1.7.2 0.3.0 FROM scratch true true");
+
+    assert!(renderer.render_str("{{ dest_file('../secret') }}").is_err());
+    assert!(renderer
+        .render_str("{{ dest_json('Dockerfile') }}")
+        .is_err());
+
+    // Before there is a destination every file is missing
+    let observed = Renderer::new(HashMap::new())
+        .render_str("{{ dest_file('Dockerfile') is none }}")
+        .unwrap();
+    assert_eq!(observed, "true");
+
+    std::fs::remove_dir_all(&dest).unwrap();
+}
+
+#[test]
+fn test_strict_undefined() {
+    let vars = HashMap::from([("project_name".to_string(), TemplateVar::from("my_project"))]);
+    let lenient = Renderer::new(vars.clone());
+    let strict = Renderer::new(vars).with_strict_undefined(true);
+
+    assert_eq!(lenient.render_str("[{{ missing }}]").unwrap(), "[]");
+    let err = strict.render_str("line one\n[{{ missing }}]").unwrap_err();
+    let RenderError::TemplateError(e) = err else {
+        panic!("expected a template error");
+    };
+    assert_eq!(e.kind(), minijinja::ErrorKind::UndefinedError);
+    assert_eq!(e.line(), Some(2));
+
+    // Checking for a variable is still allowed
+    let observed = strict
+        .render_str("{% if missing %}yes{% else %}no{% endif %} {{ missing is defined }}")
+        .unwrap();
+    assert_eq!(observed, "no false");
+}
+
+#[test]
+fn test_render_custom_syntax() {
+    let vars = HashMap::from([("project_name".to_string(), TemplateVar::from("my_project"))]);
+    let syntax = Syntax {
+        block: ("<%".to_string(), "%>".to_string()),
+        variable: ("<<".to_string(), ">>".to_string()),
+        comment: ("<#".to_string(), "#>".to_string()),
+        trim_blocks: true,
+        lstrip_blocks: true,
+        ..Syntax::default()
+    };
+    let renderer = Renderer::new(vars).with_syntax(&syntax).unwrap();
+
+    let content = dedent(
+        r#"
+        name: << project_name >>
+        run: echo ${{ github.sha }}
+          <% if true %>
+        steps: []
+          <% endif %>
+        <# a comment #>
+          << user_content("extra") >>
+        "#,
+    );
+    let file = File::new(&content);
+    let expected = dedent(
+        r#"
+        name: my_project
+        run: echo ${{ github.sha }}
+        steps: []
+          # begin-user-content:extra
+          # user content here
+          # end-user-content:extra
+        "#,
+    );
+    assert_eq!(renderer.render(&file, &file).unwrap(), expected);
+
+    // Paths use the same syntax
+    assert_eq!(
+        renderer.render_str("src/<< project_name >>").unwrap(),
+        "src/my_project"
+    );
+
+    let syntax = Syntax {
+        block: ("<<".to_string(), "%>".to_string()),
+        variable: ("<<".to_string(), ">>".to_string()),
+        ..Syntax::default()
+    };
+    assert!(Renderer::default().with_syntax(&syntax).is_err());
+}
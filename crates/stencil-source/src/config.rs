@@ -1,35 +1,390 @@
 // Copyright (c) 2024-2025 David Stanek <dstanek@dstanek.com>
 
-// TODO: support for a source configuration need to be added.
-
+use indexmap::IndexMap;
+use regex::Regex;
+use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize)]
+use stencil_error::StencilError;
+use stencil_rendering::Syntax;
+
+/// The name of the manifest file at the root of a stencil. It describes the
+/// stencil itself and is never rendered into the destination.
+pub const MANIFEST_FILENAME: &str = "stencil.toml";
+
+const DEFAULT_PARTIALS_DIR: &str = "_partials";
+
+const DEFAULT_TEMPLATE_EXTENSION: &str = "jinja";
+
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct SourceConfig {
+    #[serde(default)]
     pub stencil: SourceStencil,
+    #[serde(default)]
+    pub questions: Vec<SourceQuestion>,
+    /// Variables derived from the arguments, as Jinja expressions. They are
+    /// evaluated in order so later ones may use earlier ones.
+    #[serde(default)]
+    pub computed: IndexMap<String, String>,
+    /// Data files, by the name templates see them under as `data.<name>`.
+    /// They are parsed as TOML, JSON or YAML by their extension and never
+    /// rendered into the destination.
+    #[serde(default)]
+    pub data: IndexMap<String, String>,
+    /// The Jinja syntax of the templates, for stencils that render files
+    /// using `{{ }}` themselves. It applies to file and directory names too.
+    #[serde(default)]
+    pub syntax: Syntax,
+    #[serde(default)]
+    pub copy_without_render: CopyWithoutRender,
+    /// Optional parts of the stencil: a glob, matched against paths in the
+    /// stencil, and the Jinja expression that has to be true for them to be
+    /// rendered. A directory that is left out takes its contents with it.
+    #[serde(default)]
+    pub when: IndexMap<String, String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub struct SourceStencil {
     pub author_name: String,
     pub author_email: String,
     pub version: String,
     pub description: String,
+    /// The versions of the stencil tool able to render this stencil, as a
+    /// semver requirement like `">=0.4"`.
+    pub requires_stencil: Option<String>,
+    /// A directory of templates for includes, imports and macros. It is
+    /// never rendered into the destination. Defaults to `_partials`.
+    pub partials: Option<String>,
+    /// Environment variables templates may read with `env(...)`. Any other
+    /// variable is an error, so a stencil can't quietly read secrets.
+    pub environment: Vec<String>,
+    /// How templates treat undefined variables.
+    pub undefined: Undefined,
+    /// The extensions of files whose contents are rendered, without the dot.
+    /// The extension is removed from the rendered file. Defaults to `jinja`.
+    pub template_extensions: Option<Vec<String>>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct SourceQuestons {
-    pub questions: Vec<SourceQuestion>,
+/// Whether using an undefined variable is an error. Either way, a template
+/// may check for one with `{% if name %}` or `name is defined`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Undefined {
+    /// Printing, iterating or looking into an undefined variable is an error.
+    #[default]
+    Strict,
+    /// Undefined variables print as an empty string.
+    Lenient,
+}
+
+/// Glob patterns, matched against paths in the stencil, for files copied
+/// as they are. `*` stays within a directory and `**` crosses them. Braces
+/// are alternatives, so a literal `{` is written `\{`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CopyWithoutRender {
+    /// Files whose contents are copied unrendered, keeping their name even
+    /// if it has a template extension.
+    pub contents: Vec<String>,
+    /// Files and directories whose names are used literally, so that they
+    /// may contain `{{`.
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Datatype {
+    #[default]
+    String,
+    Int,
+    Bool,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct SourceQuestion {
     pub variable: String,
     pub question: String,
-    pub datatype: String, // TODO: constrain to a set of known types
+    #[serde(default)]
+    pub datatype: Datatype,
+    pub default: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    pub regex: Option<String>,
+    #[serde(default)]
+    pub choices: Vec<String>,
+    pub min: Option<i64>,
+    pub max: Option<i64>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct SourceOptions {
-    ignore: Vec<String>,
+impl SourceConfig {
+    pub fn parse(contents: &str) -> Result<Self, StencilError> {
+        let config: Self = toml::from_str(contents)?;
+        // An empty directory would make every file in the stencil a partial
+        if let Some(partials) = &config.stencil.partials {
+            if matches!(partials.trim_matches('/'), "" | ".") {
+                return Err(StencilError::ConfigValidation(format!(
+                    "stencil.partials: '{partials}' must name a directory in the stencil"
+                )));
+            }
+        }
+        let mut extensions = config.stencil.template_extensions.iter().flatten();
+        if let Some(ext) = extensions.find(|ext| ext.trim_start_matches('.').is_empty()) {
+            return Err(StencilError::ConfigValidation(format!(
+                "stencil.template_extensions: '{ext}' is not an extension"
+            )));
+        }
+        Ok(config)
+    }
+
+    pub fn partials_dir(&self) -> &str {
+        self.stencil
+            .partials
+            .as_deref()
+            .unwrap_or(DEFAULT_PARTIALS_DIR)
+    }
+
+    /// The template extensions, without a leading dot even if the stencil
+    /// wrote one.
+    pub fn template_extensions(&self) -> Vec<String> {
+        match &self.stencil.template_extensions {
+            Some(extensions) => extensions
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_string())
+                .collect(),
+            None => vec![DEFAULT_TEMPLATE_EXTENSION.to_string()],
+        }
+    }
+
+    pub fn question(&self, variable: &str) -> Option<&SourceQuestion> {
+        self.questions.iter().find(|q| q.variable == variable)
+    }
+
+    /// Checks that the running tool, at `version`, satisfies the stencil's
+    /// `requires_stencil`.
+    pub fn check_tool_version(&self, version: &str) -> Result<(), StencilError> {
+        let Some(requirement) = &self.stencil.requires_stencil else {
+            return Ok(());
+        };
+        let req = VersionReq::parse(requirement).map_err(|e| {
+            StencilError::ConfigValidation(format!(
+                "invalid requires_stencil '{requirement}' in the stencil: {e}"
+            ))
+        })?;
+        let version = Version::parse(version).map_err(|e| StencilError::Other(e.to_string()))?;
+        if req.matches(&version) {
+            Ok(())
+        } else {
+            Err(StencilError::ConfigValidation(format!(
+                "the stencil requires stencil {requirement} but this is version {version}; \
+                 upgrade stencil to use it"
+            )))
+        }
+    }
+}
+
+impl SourceQuestion {
+    /// Checks an argument value against the question's constraints, returning
+    /// a description of the expected value when it doesn't match.
+    pub fn check(&self, value: &str) -> Result<(), String> {
+        if self.required && value.is_empty() {
+            return Err("a value is required".to_string());
+        }
+
+        match self.datatype {
+            Datatype::String => {}
+            Datatype::Bool => {
+                if parse_bool(value).is_none() {
+                    return Err(format!("expected a boolean, got '{value}'"));
+                }
+            }
+            Datatype::Int => {
+                let Ok(i) = value.parse::<i64>() else {
+                    return Err(format!("expected an integer, got '{value}'"));
+                };
+                match (self.min, self.max) {
+                    (Some(min), Some(max)) if i < min || i > max => {
+                        return Err(format!(
+                            "expected an integer between {min} and {max}, got {i}"
+                        ));
+                    }
+                    (Some(min), None) if i < min => {
+                        return Err(format!("expected an integer of at least {min}, got {i}"));
+                    }
+                    (None, Some(max)) if i > max => {
+                        return Err(format!("expected an integer of at most {max}, got {i}"));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if !self.choices.is_empty() && !self.choices.iter().any(|c| c == value) {
+            return Err(format!(
+                "expected one of {}, got '{value}'",
+                self.choices
+                    .iter()
+                    .map(|c| format!("'{c}'"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+
+        if let Some(pattern) = &self.regex {
+            let re = Regex::new(&format!("^(?:{pattern})$"))
+                .map_err(|e| format!("invalid regex '{pattern}' in the stencil: {e}"))?;
+            if !re.is_match(value) {
+                return Err(format!(
+                    "expected a value matching '{pattern}', got '{value}'"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses the boolean spellings accepted for `bool` arguments.
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn question(toml: &str) -> SourceQuestion {
+        let config = SourceConfig::parse(&format!(
+            "[[questions]]\nvariable = \"v\"\nquestion = \"?\"\n{toml}"
+        ))
+        .unwrap();
+        config.questions.into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn test_check_types() {
+        let q = question("datatype = \"int\"\nmin = 1024\nmax = 65535");
+        assert!(q.check("8080").is_ok());
+        assert_eq!(
+            q.check("80"),
+            Err("expected an integer between 1024 and 65535, got 80".to_string())
+        );
+        assert_eq!(
+            q.check("eighty"),
+            Err("expected an integer, got 'eighty'".to_string())
+        );
+
+        let q = question("datatype = \"bool\"");
+        assert!(q.check("yes").is_ok());
+        assert!(q.check("False").is_ok());
+        assert!(q.check("maybe").is_err());
+    }
+
+    #[test]
+    fn test_template_extensions() {
+        assert_eq!(SourceConfig::default().template_extensions(), vec!["jinja"]);
+
+        let config = SourceConfig::parse(
+            "[stencil]\ntemplate_extensions = [\"j2\", \"tmpl\"]\n\
+             [copy_without_render]\ncontents = [\"vendor/**\"]",
+        )
+        .unwrap();
+        assert_eq!(config.template_extensions(), vec!["j2", "tmpl"]);
+        assert_eq!(config.copy_without_render.contents, vec!["vendor/**"]);
+        assert!(config.copy_without_render.paths.is_empty());
+
+        let config = SourceConfig::parse("[stencil]\ntemplate_extensions = [\".j2\"]").unwrap();
+        assert_eq!(config.template_extensions(), vec!["j2"]);
+        let err = SourceConfig::parse("[stencil]\ntemplate_extensions = [\".\"]").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Validation error: stencil.template_extensions: '.' is not an extension"
+        );
+    }
+
+    #[test]
+    fn test_partials_dir() {
+        assert_eq!(SourceConfig::default().partials_dir(), "_partials");
+        let config = SourceConfig::parse("[stencil]\npartials = \"shared\"").unwrap();
+        assert_eq!(config.partials_dir(), "shared");
+
+        for partials in ["", "/", "."] {
+            let err =
+                SourceConfig::parse(&format!("[stencil]\npartials = \"{partials}\"")).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "Validation error: stencil.partials: '{partials}' must name a directory in the stencil"
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_syntax() {
+        let config = SourceConfig::parse("[syntax]\nvariable = [\"<<\", \">>\"]").unwrap();
+        assert_eq!(config.syntax.variable, ("<<".to_string(), ">>".to_string()));
+        assert_eq!(config.syntax.block, ("{%".to_string(), "%}".to_string()));
+        assert_eq!(config.syntax.line_statement_prefix, None);
+
+        assert!(SourceConfig::parse("[syntax]\nvariables = [\"<<\", \">>\"]").is_err());
+    }
+
+    #[test]
+    fn test_when() {
+        let config = SourceConfig::parse(
+            r#"
+            [when]
+            Dockerfile = "use_docker"
+            "migrations" = "database != 'none'"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.when.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("Dockerfile".to_string(), "use_docker".to_string()),
+                ("migrations".to_string(), "database != 'none'".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_tool_version() {
+        let config = SourceConfig::parse("[stencil]\nrequires_stencil = \">=0.4\"").unwrap();
+        assert!(config.check_tool_version("0.4.0").is_ok());
+        assert!(config.check_tool_version("1.2.3").is_ok());
+        assert_eq!(
+            config.check_tool_version("0.3.9").unwrap_err().to_string(),
+            "Validation error: the stencil requires stencil >=0.4 but this is version 0.3.9; \
+             upgrade stencil to use it"
+        );
+
+        assert!(SourceConfig::default().check_tool_version("0.1.0").is_ok());
+        let config = SourceConfig::parse("[stencil]\nrequires_stencil = \"soon\"").unwrap();
+        assert!(config.check_tool_version("0.4.0").is_err());
+    }
+
+    #[test]
+    fn test_check_constraints() {
+        let q = question("required = true\nregex = \"[a-z_]+\"");
+        assert!(q.check("my_project").is_ok());
+        assert_eq!(q.check(""), Err("a value is required".to_string()));
+        assert_eq!(
+            q.check("my-project"),
+            Err("expected a value matching '[a-z_]+', got 'my-project'".to_string())
+        );
+
+        let q = question("choices = [\"MIT\", \"Apache-2.0\"]");
+        assert!(q.check("MIT").is_ok());
+        assert_eq!(
+            q.check("GPL"),
+            Err("expected one of 'MIT', 'Apache-2.0', got 'GPL'".to_string())
+        );
+    }
 }
@@ -1,36 +1,231 @@
 // Copyright (c) 2024-2025 David Stanek <dstanek@dstanek.com>
 
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
+use crate::config::{SourceConfig, MANIFEST_FILENAME};
 use crate::filesystem::FilesystemIterator;
 use crate::git::GithubRepoIterator;
-use crate::model::{Renderable, RenderableIterator};
+use crate::model::{Renderable, RenderableIterator, Stencil};
 use stencil_error::StencilError;
 
-pub fn renderables(source: &String) -> Result<Vec<Renderable>, StencilError> {
+/// Settings used while fetching a stencil.
+#[derive(Debug, Default)]
+pub struct LoadOptions {
+    /// Token for the GitHub API. `GITHUB_TOKEN` takes precedence when set.
+    pub github_token: Option<String>,
+}
+
+/// A stencil in a GitHub repository, written as `gh://owner/repo[/path]`.
+/// The path defaults to `stencil`.
+#[derive(Debug, PartialEq)]
+struct GithubSource {
+    owner: String,
+    repo: String,
+    path: String,
+}
+
+impl GithubSource {
+    fn parse(source: &str) -> Option<Self> {
+        let source = source.strip_prefix("gh://")?;
+        let parts: Vec<&str> = source.split('/').collect();
+        let stencil_path = if parts.len() == 3 {
+            parts[2].to_string()
+        } else {
+            "stencil".to_string()
+        };
+        Some(GithubSource {
+            owner: parts[0].to_string(),
+            repo: parts.get(1).unwrap_or(&"").to_string(),
+            path: stencil_path,
+        })
+    }
+}
+
+pub fn renderables(source: &str, options: &LoadOptions) -> Result<Vec<Renderable>, StencilError> {
+    fetch(source, options).map(|(renderables, _)| renderables)
+}
+
+// The stencil's files along with the commit they were read from, if known
+fn fetch(
+    source: &str,
+    options: &LoadOptions,
+) -> Result<(Vec<Renderable>, Option<String>), StencilError> {
     // TODO: maybe add Github Enterprise and Gitlab support?
-    let iterator: Box<dyn RenderableIterator> = match source.starts_with("gh://") {
-        true => {
-            // TODO: add support for sha pinning like gh:owner/project[/path][@sha]
-            let source = source.split("://").nth(1).unwrap_or(source);
-            let parts: Vec<&str> = source.split('/').collect();
-            let stencil_path = if parts.len() == 3 {
-                parts[2].to_string()
-            } else {
-                "stencil".to_string()
-            };
-            Box::new(GithubRepoIterator::new(
-                parts[0].to_string(),
-                parts[1].to_string(),
-                stencil_path,
-            )?)
+    let (iterator, reference): (Box<dyn RenderableIterator>, _) = match GithubSource::parse(source)
+    {
+        Some(github) => {
+            let iterator = GithubRepoIterator::new(
+                github.owner,
+                github.repo,
+                github.path,
+                options.github_token.clone(),
+            )?;
+            let reference = iterator.reference().to_string();
+            (Box::new(iterator), Some(reference))
         }
-        false => {
+        None => {
             let stencil_path = PathBuf::from(source);
-            Box::new(FilesystemIterator::new(&stencil_path)?)
+            (
+                Box::new(FilesystemIterator::new(&stencil_path)?),
+                local_reference(source),
+            )
         }
     };
 
     let renderables: Vec<Renderable> = iterator.filter_map(Result::ok).collect();
-    Ok(renderables)
+    Ok((renderables, reference))
+}
+
+// The commit a local stencil is checked out at, when it is in a git
+// repository
+fn local_reference(source: &str) -> Option<String> {
+    let output = Command::new("git")
+        .args(["-C", source, "rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    let sha = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !sha.is_empty()).then_some(sha)
+}
+
+/// Load a stencil, splitting its manifest from the files that get rendered.
+/// Stencils without a manifest get an empty configuration.
+pub fn load(source: &str, options: &LoadOptions) -> Result<Stencil, StencilError> {
+    let mut config = SourceConfig::default();
+    let mut renderables = Vec::new();
+    let (all, reference) = fetch(source, options)?;
+    for renderable in all {
+        match renderable {
+            Renderable::File(file) if file.relative_path == MANIFEST_FILENAME => {
+                config = SourceConfig::parse(&file.content)?;
+            }
+            renderable => renderables.push(renderable),
+        }
+    }
+
+    let mut templates = BTreeMap::new();
+    for renderable in &renderables {
+        if let Renderable::File(file) = renderable {
+            templates.insert(file.relative_path.clone(), file.content.clone());
+        }
+    }
+
+    let data = load_data(&config, &templates)?;
+
+    // Partials are only there to be included by other templates, and data
+    // files are only there to be read by them
+    let partials = config.partials_dir().trim_end_matches('/').to_string();
+    renderables.retain(|renderable| {
+        let path = match renderable {
+            Renderable::File(file) => &file.relative_path,
+            Renderable::Directory(directory) => &directory.relative_path,
+        };
+        !Path::new(path).starts_with(&partials) && !config.data.values().any(|p| p == path)
+    });
+
+    // Nor are the directories that only held data files
+    let files: Vec<String> = renderables
+        .iter()
+        .filter_map(|renderable| match renderable {
+            Renderable::File(file) => Some(file.relative_path.clone()),
+            Renderable::Directory(_) => None,
+        })
+        .collect();
+    renderables.retain(|renderable| match renderable {
+        Renderable::Directory(directory) => {
+            let inside = |path: &String| Path::new(path).starts_with(&directory.relative_path);
+            !config.data.values().any(inside) || files.iter().any(inside)
+        }
+        Renderable::File(_) => true,
+    });
+
+    Ok(Stencil {
+        config,
+        renderables,
+        templates,
+        data,
+        source: source.to_string(),
+        reference,
+    })
+}
+
+// Parses the data files the manifest declares
+fn load_data(
+    config: &SourceConfig,
+    files: &BTreeMap<String, String>,
+) -> Result<BTreeMap<String, serde_json::Value>, StencilError> {
+    let mut data = BTreeMap::new();
+    for (name, path) in &config.data {
+        let error = |message: String| {
+            StencilError::ConfigValidation(format!("data '{name}' ({path}): {message}"))
+        };
+        let contents = files
+            .get(path)
+            .ok_or_else(|| error("no such file in the stencil".to_string()))?;
+        let extension = Path::new(path).extension().and_then(|e| e.to_str());
+        let value = match extension {
+            Some("toml") => toml::from_str(contents).map_err(|e| error(e.to_string()))?,
+            Some("json") => serde_json::from_str(contents).map_err(|e| error(e.to_string()))?,
+            Some("yaml" | "yml") => {
+                serde_norway::from_str(contents).map_err(|e| error(e.to_string()))?
+            }
+            _ => return Err(error("expected a .toml, .json or .yaml file".to_string())),
+        };
+        data.insert(name.clone(), value);
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_github_source() {
+        assert_eq!(
+            GithubSource::parse("gh://acme/stencils/python"),
+            Some(GithubSource {
+                owner: "acme".to_string(),
+                repo: "stencils".to_string(),
+                path: "python".to_string(),
+            })
+        );
+        assert_eq!(
+            GithubSource::parse("gh://acme/stencils"),
+            Some(GithubSource {
+                owner: "acme".to_string(),
+                repo: "stencils".to_string(),
+                path: "stencil".to_string(),
+            })
+        );
+        assert_eq!(GithubSource::parse("../stencils/python"), None);
+    }
+
+    #[test]
+    fn test_load_data() {
+        let config = SourceConfig::parse(
+            "[data]\npython = \"data/python.toml\"\nmatrix = \"data/matrix.yaml\"",
+        )
+        .unwrap();
+        let files = BTreeMap::from([
+            (
+                "data/python.toml".to_string(),
+                "versions = [\"3.12\", \"3.13\"]".to_string(),
+            ),
+            (
+                "data/matrix.yaml".to_string(),
+                "os: [linux, macos]".to_string(),
+            ),
+        ]);
+        let data = load_data(&config, &files).unwrap();
+        assert_eq!(data["python"]["versions"][1], "3.13");
+        assert_eq!(data["matrix"]["os"][0], "linux");
+
+        let config = SourceConfig::parse("[data]\nmissing = \"data/missing.json\"").unwrap();
+        assert_eq!(
+            load_data(&config, &files).unwrap_err().to_string(),
+            "Validation error: data 'missing' (data/missing.json): no such file in the stencil"
+        );
+    }
 }
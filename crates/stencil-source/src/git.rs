@@ -29,18 +29,41 @@ pub struct GithubRepoIterator {
     repo: String,
     path: String,
     token: Option<String>,
+    reference: String,
     queue: VecDeque<GitHubItem>,
 }
 
+// The commit the default branch is at. The stencil is fetched from that
+// commit, so every file comes from the same point in its history
+fn get_head_commit(owner: &str, repo: &str, token: Option<&str>) -> Result<String, Error> {
+    let url = format!(
+        "https://api.github.com/repos/{}/{}/commits/HEAD",
+        owner, repo
+    );
+    let request = ureq::get(&url)
+        .header("User-Agent", "stencil")
+        .header("Accept", "application/vnd.github.sha");
+    let mut response = if let Some(token) = token {
+        request
+            .header("Authorization", &format!("Bearer {}", token))
+            .call()?
+    } else {
+        request.call()?
+    };
+    let sha = response.body_mut().read_to_string()?;
+    Ok(sha.trim().to_string())
+}
+
 fn get_directory_contents(
     owner: &str,
     repo: &str,
     path: &str,
+    reference: &str,
     token: Option<&str>,
 ) -> Result<Vec<GitHubItem>, Error> {
     let url = format!(
-        "https://api.github.com/repos/{}/{}/contents/{}",
-        owner, repo, path
+        "https://api.github.com/repos/{}/{}/contents/{}?ref={}",
+        owner, repo, path, reference
     );
     let request = ureq::get(&url).header("User-Agent", "stencil");
     let mut response = if let Some(token) = token {
@@ -79,18 +102,28 @@ impl GithubRepoIterator {
         owner: String,
         repo: String,
         path: String,
+        token: Option<String>,
         //) -> Result<GithubRepoIterator, StencilError> {
     ) -> Result<Self, StencilError> {
-        let token = env::var("GITHUB_TOKEN").ok();
-        let items = get_directory_contents(&owner, &repo, &path, token.as_deref()).unwrap();
+        let token = env::var("GITHUB_TOKEN").ok().or(token);
+        let reference = get_head_commit(&owner, &repo, token.as_deref())
+            .map_err(|e| StencilError::Other(e.to_string()))?;
+        let items =
+            get_directory_contents(&owner, &repo, &path, &reference, token.as_deref()).unwrap();
         Ok(GithubRepoIterator {
             owner,
             repo,
             path: path.clone(),
             token,
+            reference,
             queue: VecDeque::from(items),
         })
     }
+
+    /// The commit the stencil is fetched from.
+    pub fn reference(&self) -> &str {
+        &self.reference
+    }
 }
 
 impl Iterator for GithubRepoIterator {
@@ -127,6 +160,7 @@ impl Iterator for GithubRepoIterator {
                         &self.owner,
                         &self.repo,
                         &item.path,
+                        &self.reference,
                         self.token.as_deref(),
                     ) {
                         Ok(items) => items,
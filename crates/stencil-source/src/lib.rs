@@ -1,6 +1,6 @@
 // Copyright (c) 2024-2025 David Stanek <dstanek@dstanek.com>
 
-//mod config;
+mod config;
 mod factory;
 mod filesystem;
 mod git;
@@ -8,7 +8,12 @@ mod model;
 
 // public interface
 #[allow(unused_imports)]
-pub use factory::renderables;
+pub use factory::{load, renderables, LoadOptions};
 
 #[allow(unused_imports)]
-pub use model::{Directory, File, Renderable};
+pub use model::{Directory, File, Renderable, Stencil};
+
+pub use config::{
+    parse_bool, CopyWithoutRender, Datatype, SourceConfig, SourceQuestion, SourceStencil,
+    Undefined, MANIFEST_FILENAME,
+};
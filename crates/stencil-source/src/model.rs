@@ -1,13 +1,16 @@
 // Copyright (c) 2024-2025 David Stanek <dstanek@dstanek.com>
 
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 
+use crate::config::SourceConfig;
 use stencil_error::StencilError;
 
 pub trait RenderableIterator: Iterator<Item = Result<Renderable, StencilError>> {}
 impl<T> RenderableIterator for T where T: Iterator<Item = Result<Renderable, StencilError>> {}
 
+#[derive(Clone)]
 pub struct File {
     pub relative_path: String,
     pub content: String,
@@ -40,6 +43,7 @@ impl File {
     }
 }
 
+#[derive(Clone)]
 pub struct Directory {
     pub relative_path: String,
 }
@@ -50,7 +54,25 @@ impl Directory {
     }
 }
 
+#[derive(Clone)]
 pub enum Renderable {
     File(File),
     Directory(Directory),
 }
+
+/// A loaded stencil: its manifest plus everything that will be rendered.
+pub struct Stencil {
+    pub config: SourceConfig,
+    pub renderables: Vec<Renderable>,
+    /// Every file in the stencil, partials included, keyed by its path
+    /// relative to the stencil root. Templates include and import from these.
+    pub templates: BTreeMap<String, String>,
+    /// The parsed data files, by name.
+    pub data: BTreeMap<String, serde_json::Value>,
+    /// Where the stencil was loaded from.
+    pub source: String,
+    /// The commit the stencil was read from: the one a local stencil is
+    /// checked out at, when it is in git, or the one a GitHub stencil was
+    /// fetched from.
+    pub reference: Option<String>,
+}
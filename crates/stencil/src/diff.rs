@@ -3,20 +3,22 @@
 use similar::{ChangeTag, TextDiff};
 use std::io::Write;
 use std::path::Path;
-use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, StandardStreamLock, WriteColor};
+use termcolor::{Color, ColorSpec, WriteColor};
 
 use crate::output::write;
 use crate::target_config::TargetConfig;
 use stencil_error::StencilError;
 use stencil_source::{Directory, File, Renderable};
 
+const REDACTED: &str = "********";
+
 pub fn show_diff(
+    out: &mut dyn WriteColor,
     changes: &Vec<Renderable>,
-    _config: &TargetConfig,
+    config: &TargetConfig,
     dest: &Path,
 ) -> Result<(), StencilError> {
-    let stdout = StandardStream::stdout(ColorChoice::Always);
-    let mut stdout_lock = stdout.lock();
+    let secrets = config.secrets();
 
     // TODO: implement a way to ignore certain files
     // let mut ignore = Vec::new();
@@ -31,25 +33,36 @@ pub fn show_diff(
                 } else {
                     File::empty()
                 };
-                show_file_diff(&mut stdout_lock, &orig_file, file)?;
+                show_file_diff(
+                    out,
+                    &redact(orig_file, &secrets),
+                    &redact(file.clone(), &secrets),
+                )?;
             }
             Renderable::Directory(dir) => {
                 if !dest.join(&dir.relative_path).exists() {
-                    show_directory_diff(&mut stdout_lock, dir)?;
-                };
+                    show_directory_diff(out, dir)?;
+                }
             }
         }
     }
     Ok(())
 }
 
+// Secret argument values must never end up in the terminal or CI logs
+fn redact(mut file: File, secrets: &[String]) -> File {
+    for secret in secrets {
+        file.content = file.content.replace(secret, REDACTED);
+    }
+    file
+}
+
 fn show_file_diff(
-    mut handle: &mut StandardStreamLock,
+    mut handle: &mut dyn WriteColor,
     old: &File,
     new: &File,
 ) -> Result<(), StencilError> {
     let old_path = &old.relative_path;
-    let new_path = &new.relative_path;
     let old_content = old.content.as_str();
     let new_content = new.content.as_str();
 
@@ -59,31 +72,7 @@ fn show_file_diff(
     }
     // Compute the diff between the two files
     let diff = TextDiff::from_lines(old_content, new_content);
-
-    // Print the diff header
-    write(
-        &mut handle,
-        Color::Yellow,
-        format!("diff --git a/{new_path} b/{new_path}\n").as_str(),
-    )?;
-    if old_path == "/dev/null" {
-        write(
-            &mut handle,
-            Color::Blue,
-            format!("--- old/{new_path}    (file not found)\n").as_str(),
-        )?;
-    } else {
-        write(
-            &mut handle,
-            Color::Blue,
-            format!("--- old/{old_path}\n").as_str(),
-        )?;
-    }
-    if new_content.is_empty() {
-        writeln!(&mut handle, "+++ new/{new_path}    (new empty file)")?;
-    } else {
-        writeln!(&mut handle, "+++ new/{new_path}")?;
-    }
+    show_file_header(handle, old, new)?;
 
     // Iterate over the diff hunks
     for group in &diff.grouped_ops(3) {
@@ -160,12 +149,45 @@ fn show_file_diff(
         }
         handle.reset()?;
     }
-    println!();
+    writeln!(&mut handle)?;
+    Ok(())
+}
+
+fn show_file_header(
+    mut handle: &mut dyn WriteColor,
+    old: &File,
+    new: &File,
+) -> Result<(), StencilError> {
+    let old_path = &old.relative_path;
+    let new_path = &new.relative_path;
+    write(
+        &mut handle,
+        Color::Yellow,
+        format!("diff --git a/{new_path} b/{new_path}\n").as_str(),
+    )?;
+    if old_path == "/dev/null" {
+        write(
+            &mut handle,
+            Color::Blue,
+            format!("--- old/{new_path}    (file not found)\n").as_str(),
+        )?;
+    } else {
+        write(
+            &mut handle,
+            Color::Blue,
+            format!("--- old/{old_path}\n").as_str(),
+        )?;
+    }
+    if new.content.is_empty() {
+        writeln!(&mut handle, "+++ new/{new_path}    (new empty file)")?;
+    } else {
+        writeln!(&mut handle, "+++ new/{new_path}")?;
+    }
     Ok(())
 }
 
 fn show_directory_diff(
-    mut handle: &mut StandardStreamLock,
+    mut handle: &mut dyn WriteColor,
     dir: &Directory,
 ) -> Result<(), StencilError> {
     write(
@@ -0,0 +1,193 @@
+// Copyright (c) 2025 David Stanek <dstanek@dstanek.com>
+
+use std::collections::BTreeMap;
+
+use regex::Regex;
+
+use stencil_error::StencilError;
+
+/// Resolves `{{ key }}` references between config values. A key is either
+/// another config key, like `project.name` or `arguments.license`, or
+/// `env.NAME` for an environment variable. Values are resolved in dependency order, so a value
+/// may reference one that has references of its own.
+///
+/// `values` maps each key to its raw value, or to `None` for secrets, which
+/// can't be referenced.
+pub fn interpolate<F>(
+    values: &BTreeMap<String, Option<String>>,
+    env: F,
+) -> Result<BTreeMap<String, String>, StencilError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut interpolator = Interpolator {
+        values,
+        env,
+        // Only names in these namespaces are references, so template
+        // expressions like `{{ name | upper }}` and other tools' syntax, like
+        // GitHub Actions' `${{ github.ref }}`, are passed through untouched
+        reference: Regex::new(r"\{\{\s*((?:project|arguments|env)\.[\w-]+)\s*\}\}").unwrap(),
+        resolved: BTreeMap::new(),
+        chain: Vec::new(),
+    };
+    for (key, value) in values {
+        if value.is_some() {
+            interpolator.resolve(key)?;
+        }
+    }
+    Ok(interpolator.resolved)
+}
+
+struct Interpolator<'a, F> {
+    values: &'a BTreeMap<String, Option<String>>,
+    env: F,
+    reference: Regex,
+    resolved: BTreeMap<String, String>,
+    // The keys currently being resolved, used to report cycles
+    chain: Vec<String>,
+}
+
+impl<F> Interpolator<'_, F>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    fn resolve(&mut self, key: &str) -> Result<String, StencilError> {
+        if let Some(value) = self.resolved.get(key) {
+            return Ok(value.clone());
+        }
+        if let Some(start) = self.chain.iter().position(|k| k == key) {
+            let mut cycle = self.chain[start..].to_vec();
+            cycle.push(key.to_string());
+            return Err(StencilError::ConfigValidation(format!(
+                "interpolation cycle: {}",
+                cycle.join(" -> ")
+            )));
+        }
+
+        let values = self.values;
+        let Some(Some(value)) = values.get(key) else {
+            unreachable!("only keys with values are resolved");
+        };
+        self.chain.push(key.to_string());
+
+        let references: Vec<_> = self
+            .reference
+            .captures_iter(value)
+            .map(|captures| (captures.get(0).unwrap().range(), captures[1].to_string()))
+            .collect();
+        let mut result = String::new();
+        let mut last = 0;
+        for (range, reference) in references {
+            result.push_str(&value[last..range.start]);
+            result.push_str(&self.replacement(&reference)?);
+            last = range.end;
+        }
+        result.push_str(&value[last..]);
+
+        self.chain.pop();
+        self.resolved.insert(key.to_string(), result.clone());
+        Ok(result)
+    }
+
+    fn replacement(&mut self, reference: &str) -> Result<String, StencilError> {
+        if let Some(name) = reference.strip_prefix("env.") {
+            return (self.env)(name)
+                .ok_or_else(|| self.error(&format!("environment variable '{name}' is not set")));
+        }
+        match self.values.get(reference) {
+            Some(Some(_)) => self.resolve(reference),
+            Some(None) => Err(self.error(&format!(
+                "'{reference}' is a secret and can't be referenced"
+            ))),
+            None => Err(self.error(&format!("unknown key '{reference}'"))),
+        }
+    }
+
+    fn error(&self, message: &str) -> StencilError {
+        StencilError::ConfigValidation(format!("{}: {message}", self.chain.join(" -> ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, Option<&str>)]) -> BTreeMap<String, Option<String>> {
+        pairs
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), v.map(str::to_string)))
+            .collect()
+    }
+
+    fn env(name: &str) -> Option<String> {
+        (name == "REGISTRY").then(|| "ghcr.io".to_string())
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let resolved = interpolate(
+            &values(&[
+                ("project.name", Some("my_project")),
+                (
+                    "arguments.image",
+                    Some("{{ arguments.registry }}/{{project.name}}"),
+                ),
+                ("arguments.registry", Some("{{ env.REGISTRY }}/acme")),
+                ("arguments.title", Some("{{ name | upper }}")),
+                ("arguments.workflow_if", Some("${{ github.ref }}")),
+                ("arguments.token", None),
+            ]),
+            env,
+        )
+        .unwrap();
+        assert_eq!(resolved["arguments.image"], "ghcr.io/acme/my_project");
+        assert_eq!(resolved["arguments.title"], "{{ name | upper }}");
+        assert_eq!(resolved["arguments.workflow_if"], "${{ github.ref }}");
+        assert!(!resolved.contains_key("arguments.token"));
+    }
+
+    #[test]
+    fn test_interpolate_errors() {
+        let err = interpolate(
+            &values(&[
+                ("arguments.a", Some("{{ arguments.b }}")),
+                ("arguments.b", Some("x-{{ arguments.c }}")),
+                ("arguments.c", Some("{{ arguments.a }}")),
+            ]),
+            env,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Validation error: interpolation cycle: \
+             arguments.a -> arguments.b -> arguments.c -> arguments.a"
+        );
+
+        let err = interpolate(
+            &values(&[
+                ("arguments.a", Some("{{ arguments.b }}")),
+                ("arguments.b", Some("{{ project.nmae }}")),
+            ]),
+            env,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Validation error: arguments.a -> arguments.b: unknown key 'project.nmae'"
+        );
+
+        let err = interpolate(
+            &values(&[
+                ("arguments.a", Some("{{ arguments.token }}")),
+                ("arguments.b", Some("{{ env.MISSING }}")),
+                ("arguments.token", None),
+            ]),
+            env,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Validation error: arguments.a: 'arguments.token' is a secret and can't be referenced"
+        );
+    }
+}
@@ -9,21 +9,28 @@
 
 use std::collections::BTreeMap;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io::{self, IsTerminal};
+use std::path::{Component, Path, PathBuf};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Result};
 use clap::{CommandFactory, Parser, Subcommand};
 use termcolor::{Color, ColorChoice, StandardStream};
 
 mod diff;
+mod interpolation;
 mod output;
+mod questions;
 mod render;
 mod target_config;
+mod user_config;
 
 use render::RenderingIterator;
 use stencil_error::StencilError;
-use stencil_source::{renderables, Renderable};
-use target_config::TargetConfig;
+use stencil_source::{LoadOptions, Renderable, Stencil};
+use target_config::{
+    closest_question, unknown_argument, Layer, TargetConfig, CONFIG_FILENAME, SCHEMA_VERSION,
+};
+use user_config::UserConfig;
 
 #[derive(Parser)]
 #[command(name = "stencil")]
@@ -32,14 +39,26 @@ struct Cli {
     #[arg(
         short,
         long,
-        help = "Path to the configuration file",
-        default_value = ".stencil.toml"
+        help = "Path to the configuration file, relative to the destination [default: nearest .stencil.toml]"
     )]
-    config: String,
+    config: Option<String>,
 
     #[arg(short, long = "override", help = "Override configuration value")]
     override_values: Vec<String>,
 
+    #[arg(
+        long,
+        global = true,
+        help = "Lay a profile's arguments from the config over the base arguments"
+    )]
+    profile: Option<String>,
+
+    #[arg(
+        long = "explain-config",
+        help = "Show which layer each configuration value came from"
+    )]
+    explain_config: bool,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -49,6 +68,8 @@ enum Commands {
     Init(InitArgs),
     Plan(PlanArgs),
     Apply(ApplyArgs),
+    /// Read and edit the project config
+    Config(ConfigArgs),
 }
 
 #[derive(Parser)]
@@ -66,8 +87,16 @@ struct InitArgs {
     )]
     show_diff: bool,
 
+    #[arg(
+        long = "diff",
+        help = "Show diff output even when the user config disables it",
+        conflicts_with = "show_diff"
+    )]
+    force_diff: bool,
+
     #[arg(
         short = 'a',
+        short_alias = 'A',
         long = "argument",
         help = "Argument to pass to the template",
         value_parser = parse_key_value,
@@ -97,6 +126,54 @@ struct ApplyArgs {
 
     #[arg(long = "no-diff", help = "Disable diff output", action = clap::ArgAction::SetFalse)]
     show_diff: bool,
+
+    #[arg(
+        long = "diff",
+        help = "Show diff output even when the user config disables it",
+        conflicts_with = "show_diff"
+    )]
+    force_diff: bool,
+
+    #[arg(
+        short = 'A',
+        long = "argument",
+        help = "Answer to a question the stencil added since the last apply",
+        value_parser = parse_key_value,
+        value_name = "KEY=VALUE",
+    )]
+    arguments: Vec<(String, String)>,
+}
+
+#[derive(Parser)]
+struct ConfigArgs {
+    #[arg(long, global = true, help = "Destination path")]
+    dest: Option<String>,
+
+    #[command(subcommand)]
+    action: ConfigAction,
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print the value of a key, like `project.src` or `arguments.license`
+    Get { key: String },
+    /// Set a value, checking arguments against the stencil's questions
+    Set {
+        key: String,
+        value: String,
+        #[arg(long, help = "Plan the changes after updating the config")]
+        plan: bool,
+    },
+    /// Remove an argument
+    Unset {
+        key: String,
+        #[arg(long, help = "Plan the changes after updating the config")]
+        plan: bool,
+    },
+    /// Print every value in the config
+    List,
+    /// Rewrite the config in the current schema version
+    Migrate,
 }
 
 fn main() {
@@ -114,45 +191,37 @@ fn run() -> Result<()> {
     let cli = Cli::parse();
     match &cli.command {
         Some(Commands::Init(args)) => {
+            let user = UserConfig::load()?;
             let dest = PathBuf::from(&args.dest);
-            init(args.show_diff, &dest, &args.src, args)?;
+            let config_path = dest.join(cli.config.as_deref().unwrap_or(CONFIG_FILENAME));
+            let show_diff = args.force_diff || (args.show_diff && !user.no_diff);
+            init(show_diff, &dest, &config_path, &args.src, args, &user)?;
         }
         Some(Commands::Plan(args)) => {
-            let dest = match &args.dest {
-                Some(dest) => PathBuf::from(dest),
-                None => std::env::current_dir()?,
-            };
-            let config_path = dest.join(&cli.config);
-            let mut config = TargetConfig::load(config_path.to_str().unwrap())
-                .context(format!("loading config file: {}", config_path.display()))?;
-            match config.apply_overrides(cli.override_values) {
-                Ok(config) => config,
-                Err(e) => {
-                    eprintln!("Error: {e}");
-                    std::process::exit(1);
-                }
-            }
-            plan(&config, &dest)?;
+            let user = UserConfig::load()?;
+            let (config_path, root) = find_project(args.dest.as_ref(), cli.config.as_ref())?;
+            let mut config = load_config(&config_path, &cli)?;
+            let dest = config.output_dir(&root)?;
+            let project = project_dir(&config_path);
+            plan(&mut config, project, &dest, cli.explain_config, &user)?;
         }
         Some(Commands::Apply(args)) => {
-            let dest = match &args.dest {
-                Some(dest) => PathBuf::from(dest),
-                None => std::env::current_dir()?,
-            };
-            let config_path = dest.join(&cli.config);
-            let mut config = TargetConfig::load(config_path.to_str().unwrap())?;
-            match config.apply_overrides(cli.override_values) {
-                Ok(config) => config,
-                Err(e) => {
-                    eprintln!("Error: {e}");
-                    std::process::exit(1);
-                }
-            }
-            let dest = match &args.dest {
-                Some(dest) => PathBuf::from(dest),
-                None => std::env::current_dir()?,
-            };
-            apply(&config, &dest, args.show_diff, args.auto_approve)?;
+            let user = UserConfig::load()?;
+            let (config_path, root) = find_project(args.dest.as_ref(), cli.config.as_ref())?;
+            let mut config = load_config(&config_path, &cli)?;
+            let dest = config.output_dir(&root)?;
+            apply(
+                &mut config,
+                &config_path,
+                &dest,
+                args,
+                cli.explain_config,
+                &user,
+            )?;
+        }
+        Some(Commands::Config(args)) => {
+            let (config_path, dest) = find_project(args.dest.as_ref(), cli.config.as_ref())?;
+            config(&cli, &config_path, &dest, &args.action)?;
         }
         None => Cli::command().print_long_help().unwrap(),
     }
@@ -160,7 +229,65 @@ fn run() -> Result<()> {
     Ok(())
 }
 
-fn init(show_diff: bool, dest: &PathBuf, src: &str, args: &InitArgs) -> Result<(), StencilError> {
+/// Finds the project config and the project's root directory, starting
+/// from `dest` or the current directory. An explicit `--config` is relative
+/// to the destination, otherwise the nearest config in the destination or
+/// one of its parents is used, the way git finds its repository.
+fn find_project(dest: Option<&String>, config: Option<&String>) -> Result<(PathBuf, PathBuf)> {
+    let dest = match dest {
+        Some(dest) => PathBuf::from(dest),
+        None => std::env::current_dir()?,
+    };
+    if let Some(config) = config {
+        return Ok((dest.join(config), dest));
+    }
+    target_config::find_project(&dest).ok_or_else(|| {
+        anyhow!(
+            "no {CONFIG_FILENAME} found in {} or any of its parent directories",
+            dest.display()
+        )
+    })
+}
+
+/// Load the project config file on its own, as it is saved.
+fn load_saved_config(config_path: &Path) -> Result<TargetConfig> {
+    TargetConfig::load(config_path.to_str().unwrap())
+        .map_err(|e| anyhow!("loading config file: {}: {e}", config_path.display()))
+}
+
+/// Load the project config and layer the environment and `--override` values
+/// on top of it.
+fn load_config(config_path: &Path, cli: &Cli) -> Result<TargetConfig> {
+    let mut config = load_saved_config(config_path)?;
+    if let Some(version) = config.migrated_from {
+        let mut stderr = StandardStream::stderr(ColorChoice::Auto);
+        output::write(
+            &mut stderr,
+            Color::Yellow,
+            format!(
+                "Warning: {} uses schema version {version}; run 'stencil config migrate' to update it\n",
+                config_path.display()
+            )
+            .as_str(),
+        )?;
+    }
+    if let Some(profile) = &cli.profile {
+        config.apply_profile(profile)?;
+    }
+    config.apply_environment(std::env::vars())?;
+    config.apply_overrides(cli.override_values.clone())?;
+    config.interpolate(|name| std::env::var(name).ok())?;
+    Ok(config)
+}
+
+fn init(
+    show_diff: bool,
+    dest: &PathBuf,
+    config_path: &PathBuf,
+    src: &str,
+    args: &InitArgs,
+    user: &UserConfig,
+) -> Result<(), StencilError> {
     println!("Initializing {}", dest.display());
 
     // Fail if the dest already exists
@@ -170,42 +297,37 @@ fn init(show_diff: bool, dest: &PathBuf, src: &str, args: &InitArgs) -> Result<(
         ));
     }
 
+    // Create the initial config file. A relative local source is recorded
+    // relative to the project, which is where later commands look for it
+    let src = project_relative_source(src, project_dir(config_path));
+    let mut config = TargetConfig::new("my_project", &src);
+    for (key, value) in &args.arguments {
+        config.set(&format!("arguments.{key}"), value.clone(), Layer::Argument)?;
+    }
+
+    // Every question is new to a brand new project
+    let stencil = load_stencil(&config, project_dir(config_path), user)?;
+    for (key, value, layer) in ask_new_questions(&stencil, &config, &[], true, user)? {
+        config.set(&format!("arguments.{key}"), value, layer)?;
+    }
+    config.validate_arguments(&stencil.config)?;
+
+    let (changes, generated) = render_changes(stencil, &config, dest, user)?;
+    config.generated = generated;
+
     // Create the destination directory
     fs::create_dir_all(dest)?;
 
-    // TODO: ask questions from the source config
-
-    // Create the initial config file
-    let mut arguments = BTreeMap::new();
-    for (key, value) in &args.arguments {
-        arguments.insert(key.clone(), value.clone());
-    }
-
-    let config = TargetConfig {
-        stencil: target_config::ConfigStencil {
-            version: "1".to_string(),
-        },
-        project: target_config::ConfigProject {
-            name: "my_project".to_string(),
-            src: src.to_string(),
-        },
-        arguments,
-    };
-    let mut config_path = PathBuf::from(dest);
-    config_path.push(".stencil.toml");
-    config.save(&config_path)?;
-
-    let iterator = create_iterator(&config)?;
-    let changes = iterator
-        .map(|result| result.unwrap())
-        .collect::<Vec<Renderable>>();
     // Show diff and apply the changes
     if show_diff {
-        diff::show_diff(&changes, &config, dest)?;
+        show_diff_paged(&changes, &config, dest, user)?;
     }
-    apply_changes(dest, &config)?;
+    apply_changes(dest, changes)?;
+
+    // Saved last since the config may be embedded in a file the stencil renders
+    config.save(config_path)?;
 
-    let mut stdout = StandardStream::stdout(ColorChoice::Always);
+    let mut stdout = StandardStream::stdout(user.color_choice(ColorChoice::Always));
     output::write_bold(
         &mut stdout,
         Color::Green,
@@ -214,22 +336,56 @@ fn init(show_diff: bool, dest: &PathBuf, src: &str, args: &InitArgs) -> Result<(
     Ok(())
 }
 
-fn plan(config: &TargetConfig, dest: &Path) -> Result<(), StencilError> {
+fn plan(
+    config: &mut TargetConfig,
+    project: &Path,
+    dest: &Path,
+    explain: bool,
+    user: &UserConfig,
+) -> Result<(), StencilError> {
     println!("Planning {} changes", dest.display());
-    let iterator = create_iterator(config)?;
-    let changes = iterator
-        .map(|result| result.unwrap())
-        .collect::<Vec<Renderable>>();
+    let stencil = load_stencil(config, project, user)?;
+    report_stale_arguments(&stencil, config)?;
+
+    // Nothing is saved during a plan so new questions only use their defaults
+    let new_questions = questions::new_questions(&stencil.config, config);
+    if !new_questions.is_empty() {
+        let names = new_questions
+            .iter()
+            .map(|q| q.variable.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!("The stencil has new questions that will be asked on apply: {names}");
+        for question in new_questions {
+            if let Some(default) = &question.default {
+                let key = format!("arguments.{}", question.variable);
+                let layer = if user.arguments.contains_key(&question.variable) {
+                    Layer::UserConfig
+                } else {
+                    Layer::StencilDefault
+                };
+                config.set(&key, default.clone(), layer)?;
+            }
+        }
+    }
+    if explain {
+        explain_config(config);
+    }
+
+    config.validate_arguments(&stencil.config)?;
+    let (changes, _) = render_changes(stencil, config, dest, user)?;
     show(config);
-    diff::show_diff(&changes, config, dest)?;
+    show_diff_paged(&changes, config, dest, user)?;
     Ok(())
 }
 
 fn apply(
-    config: &TargetConfig,
+    config: &mut TargetConfig,
+    config_path: &Path,
     dest: &Path,
-    show_diff: bool,
-    _auto_approve: bool,
+    args: &ApplyArgs,
+    explain: bool,
+    user: &UserConfig,
 ) -> Result<(), StencilError> {
     println!(
         "Applying changes from {} to {}",
@@ -237,16 +393,288 @@ fn apply(
         dest.display()
     );
     println!("Syncing {} from {}", dest.display(), config.project.src);
-    let iterator = create_iterator(config)?;
-    let changes = iterator
-        .map(|result| result.unwrap())
-        .collect::<Vec<Renderable>>();
-    if show_diff {
-        diff::show_diff(&changes, config, dest)?;
+    let stencil = load_stencil(config, project_dir(config_path), user)?;
+    report_stale_arguments(&stencil, config)?;
+
+    let answers = ask_new_questions(&stencil, config, &args.arguments, !args.auto_approve, user)?;
+    for (key, value, layer) in &answers {
+        config.set(&format!("arguments.{key}"), value.clone(), layer.clone())?;
+    }
+    if explain {
+        explain_config(config);
     }
+    config.validate_arguments(&stencil.config)?;
+
+    let (changes, generated) = render_changes(stencil, config, dest, user)?;
+    if args.force_diff || (args.show_diff && !user.no_diff) {
+        show_diff_paged(&changes, config, dest, user)?;
+    }
+    // Reload so that command line overrides don't end up in the saved config.
+    // This happens first because a manifest holding an embedded config may
+    // itself be rendered by the stencil.
+    let mut saved = TargetConfig::load(config_path.to_str().unwrap())?;
+    let embedded = config_path.file_name() != Some(CONFIG_FILENAME.as_ref());
+
     // 1. display diff
     // 2. run apply
-    apply_changes(dest, config)
+    apply_changes(dest, changes)?;
+
+    if embedded || !answers.is_empty() || generated != config.generated {
+        saved
+            .arguments
+            .extend(answers.into_iter().map(|(k, v, _)| (k, v.into())));
+        saved.generated = generated;
+        saved.save(&config_path.to_path_buf())?;
+    }
+    Ok(())
+}
+
+fn config(cli: &Cli, config_path: &Path, dest: &Path, action: &ConfigAction) -> Result<()> {
+    let (key, plan_after) = match action {
+        ConfigAction::Get { key } => {
+            let config = load_config(config_path, cli)?;
+            let Some(value) = config.get(key) else {
+                let message = format!("'{key}' is not set");
+                return Err(StencilError::ConfigValidation(message).into());
+            };
+            println!("{value}");
+            return Ok(());
+        }
+        ConfigAction::List => {
+            let config = load_config(config_path, cli)?;
+            for (key, value, _) in config.explain() {
+                println!("{key} = {value:?}");
+            }
+            return Ok(());
+        }
+        ConfigAction::Migrate => {
+            let config = load_saved_config(config_path)?;
+            match config.migrated_from {
+                Some(version) => {
+                    config.save(&config_path.to_path_buf())?;
+                    println!(
+                        "Migrated {} from schema version {version} to {SCHEMA_VERSION}",
+                        config_path.display()
+                    );
+                }
+                None => println!(
+                    "{} is already at schema version {SCHEMA_VERSION}",
+                    config_path.display()
+                ),
+            }
+            return Ok(());
+        }
+        ConfigAction::Set { key, plan, .. } | ConfigAction::Unset { key, plan } => (key, *plan),
+    };
+
+    // Only edits need the stencil, so a broken user config can still be
+    // read and fixed with the other actions
+    let user = &UserConfig::load()?;
+
+    // Edits go to the file alone, without the environment or overrides
+    let mut saved = load_saved_config(config_path)?;
+    match action {
+        ConfigAction::Set { value, .. } => {
+            saved.set(key, value.clone(), Layer::ConfigFile)?;
+            // Loading the stencil also checks that a new source exists
+            let stencil = load_stencil(&saved, project_dir(config_path), user)?;
+            if let Some(variable) = key.strip_prefix("arguments.") {
+                match stencil.config.question(variable) {
+                    Some(question) => question
+                        .check(value)
+                        .map_err(|e| StencilError::ConfigValidation(format!("{key}: {e}")))?,
+                    None if !stencil.config.questions.is_empty() => {
+                        let message = unknown_argument(variable, &stencil.config);
+                        return Err(StencilError::ConfigValidation(message).into());
+                    }
+                    None => {}
+                }
+            }
+        }
+        _ => saved.unset(key)?,
+    }
+    saved.save(&config_path.to_path_buf())?;
+
+    if plan_after {
+        let mut config = load_config(config_path, cli)?;
+        let dest = config.output_dir(dest)?;
+        plan(
+            &mut config,
+            project_dir(config_path),
+            &dest,
+            cli.explain_config,
+            user,
+        )?;
+    }
+    Ok(())
+}
+
+/// Load the project's stencil, expanding registry aliases and using the
+/// user's credentials.
+/// The directory holding the project config, which relative stencil
+/// sources are found from.
+fn project_dir(config_path: &Path) -> &Path {
+    config_path.parent().unwrap_or(Path::new("."))
+}
+
+// The path from the project to a local stencil given relative to the current
+// directory. Anything else is kept as it is
+fn project_relative_source(src: &str, project: &Path) -> String {
+    let path = Path::new(src);
+    let Ok(cwd) = std::env::current_dir() else {
+        return src.to_string();
+    };
+    if src.contains("://") || path.is_absolute() || !path.exists() {
+        return src.to_string();
+    }
+    let from = normalize(&cwd.join(project));
+    let to = normalize(&cwd.join(path));
+    let common = from
+        .components()
+        .zip(to.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut relative = PathBuf::new();
+    for _ in from.components().skip(common) {
+        relative.push("..");
+    }
+    relative.extend(to.components().skip(common));
+    if relative.as_os_str().is_empty() {
+        ".".to_string()
+    } else {
+        relative.to_string_lossy().into_owned()
+    }
+}
+
+// Resolves `.` and `..` without touching the filesystem, since the project
+// doesn't exist yet when it is initialized
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn load_stencil(
+    config: &TargetConfig,
+    project: &Path,
+    user: &UserConfig,
+) -> Result<Stencil, StencilError> {
+    let src = user.resolve_source(&config.project.src);
+    let options = LoadOptions {
+        github_token: user.token_for("github.com"),
+    };
+
+    // A relative local source is looked for in the project first, so that
+    // commands work from anywhere inside it. Otherwise it is relative to the
+    // current directory, like the path `init` was given
+    let local = normalize(&project.join(&src));
+    let path = if !src.contains("://") && Path::new(&src).is_relative() && local.exists() {
+        local.to_string_lossy().into_owned()
+    } else {
+        src.clone()
+    };
+    let mut stencil = stencil_source::load(&path, &options)?;
+    // Templates see the source as configured, wherever stencil runs from
+    stencil.source = src;
+
+    // Checked before anything is rendered, since an older binary may not
+    // know the template functions the stencil uses
+    stencil
+        .config
+        .check_tool_version(env!("CARGO_PKG_VERSION"))?;
+
+    // The user's default answers replace the stencil's own defaults
+    for question in &mut stencil.config.questions {
+        if let Some(default) = user.arguments.get(&question.variable) {
+            question.default = Some(default.clone());
+        }
+    }
+    Ok(stencil)
+}
+
+fn show_diff_paged(
+    changes: &Vec<Renderable>,
+    config: &TargetConfig,
+    dest: &Path,
+    user: &UserConfig,
+) -> Result<(), StencilError> {
+    let color = user.color_choice(ColorChoice::Always);
+    output::paged(user.pager.as_deref(), color, |out| {
+        diff::show_diff(out, changes, config, dest)
+    })
+}
+
+/// Answer the stencil's questions that the project config doesn't answer yet.
+/// The user is only prompted when stdin is a terminal and prompting is allowed.
+fn ask_new_questions(
+    stencil: &Stencil,
+    config: &TargetConfig,
+    provided: &[(String, String)],
+    allow_prompt: bool,
+    user: &UserConfig,
+) -> Result<Vec<(String, String, Layer)>, StencilError> {
+    let new_questions = questions::new_questions(&stencil.config, config);
+    if new_questions.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let stdin = io::stdin();
+    let answers = if allow_prompt && stdin.is_terminal() {
+        let mut reader = stdin.lock();
+        let mut writer = io::stdout();
+        questions::answer(&new_questions, provided, Some((&mut reader, &mut writer)))?
+    } else {
+        questions::answer(&new_questions, provided, None)?
+    };
+    // Defaults from the user config replace the stencil's in load_stencil
+    Ok(answers
+        .into_iter()
+        .map(|(key, value, layer)| match layer {
+            Layer::StencilDefault if user.arguments.contains_key(&key) => {
+                (key, value, Layer::UserConfig)
+            }
+            _ => (key, value, layer),
+        })
+        .collect())
+}
+
+// Arguments a newer stencil no longer asks for are harmless, so they are only
+// pointed out. Likely typos are validation errors instead
+fn report_stale_arguments(stencil: &Stencil, config: &TargetConfig) -> Result<(), StencilError> {
+    let mut stderr = StandardStream::stderr(ColorChoice::Auto);
+    for key in questions::stale_arguments(&stencil.config, config) {
+        if closest_question(key, &stencil.config).is_some() {
+            continue;
+        }
+        output::write(
+            &mut stderr,
+            Color::Yellow,
+            format!(
+                "Warning: argument '{key}' is no longer used by the stencil; \
+                 remove it with 'stencil config unset arguments.{key}'\n"
+            )
+            .as_str(),
+        )?;
+    }
+    Ok(())
+}
+
+fn explain_config(config: &TargetConfig) {
+    println!("\nConfiguration layers:");
+    for (key, value, layer) in config.explain() {
+        match layer {
+            Some(layer) => println!("  {key} = {value:?}    ({layer})"),
+            None => println!("  {key} = {value:?}    (unknown origin)"),
+        }
+    }
 }
 
 fn show(config: &TargetConfig) {
@@ -254,21 +682,26 @@ fn show(config: &TargetConfig) {
     println!("  Stencil:version : {:?}", config.stencil.version);
     println!("  Project:name: {:?}", config.project.name);
     println!("  Project:src: {:?}", config.project.src);
+    for (key, value) in &config.arguments {
+        // Secret arguments display as redacted references to their source
+        println!("  Arguments:{key}: {value}");
+    }
 }
 
 // An iterator that wraps FilesystemIterator and applies the rendering logic
 
-fn apply_changes(dest: &Path, config: &TargetConfig) -> Result<(), StencilError> {
-    let iterator = create_iterator(config)?;
-    for entry in iterator {
+fn apply_changes(dest: &Path, changes: Vec<Renderable>) -> Result<(), StencilError> {
+    // A profile may render into a directory that doesn't exist yet
+    fs::create_dir_all(dest)?;
+    for entry in changes {
         match entry {
-            Ok(Renderable::Directory(dir)) => {
+            Renderable::Directory(dir) => {
                 let path = dest.join(&dir.relative_path);
                 // println!("Creating directory: {:?}", path);
                 fs::create_dir_all(path)?;
                 // println!("Successfully created directory: {:?}", path);
             }
-            Ok(Renderable::File(file)) => {
+            Renderable::File(file) => {
                 let path = dest.join(&file.relative_path);
                 // println!("Creating file: {:?}", path);
                 fs::write(path, file.content)?;
@@ -281,7 +714,6 @@ fn apply_changes(dest: &Path, config: &TargetConfig) -> Result<(), StencilError>
                 //    &Renderable::File(file),
                 //)?;
             }
-            Err(e) => return Err(e),
         }
     }
     Ok(())
@@ -325,9 +757,40 @@ fn apply_changes(dest: &Path, config: &TargetConfig) -> Result<(), StencilError>
 // }
 //}
 
-pub fn create_iterator(config: &TargetConfig) -> Result<RenderingIterator, StencilError> {
-    let iterator = renderables(&config.project.src)?;
-    Ok(RenderingIterator::new(iterator, config))
+/// Render the stencil, returning the changes along with the generated values
+/// they use.
+fn render_changes(
+    stencil: Stencil,
+    config: &TargetConfig,
+    dest: &Path,
+    user: &UserConfig,
+) -> Result<(Vec<Renderable>, BTreeMap<String, String>), StencilError> {
+    let mut iterator = create_iterator(stencil, config, dest, user)?;
+
+    // Every file is rendered so that all of the errors are reported at once
+    let mut changes = Vec::new();
+    let mut failures = Vec::new();
+    for result in iterator.by_ref() {
+        match result {
+            Ok(change) => changes.push(change),
+            Err(StencilError::Render(failure)) => failures.push(failure),
+            Err(e) => return Err(e),
+        }
+    }
+    match failures.len() {
+        0 => Ok((changes, iterator.generated())),
+        1 => Err(StencilError::Render(failures.remove(0))),
+        _ => Err(StencilError::Renders(failures)),
+    }
+}
+
+fn create_iterator(
+    stencil: Stencil,
+    config: &TargetConfig,
+    dest: &Path,
+    user: &UserConfig,
+) -> Result<RenderingIterator, StencilError> {
+    RenderingIterator::new(stencil, config, dest, user)
 
     //let mut ignore = Vec::new();
     // ignore.push(".gitignore".to_string());
@@ -370,8 +833,7 @@ fn parse_key_value(s: &str) -> Result<(String, String), String> {
             }
         }
         _ => Err(format!(
-            "Invalid argument format: '{}'. Expected format: key=value",
-            s
+            "Invalid argument format: '{s}'. Expected format: key=value"
         )),
     }
 }
@@ -392,13 +854,64 @@ mod tests {
         );
         assert_eq!(
             parse_key_value("key=").unwrap(),
-            ("key".to_string(), "".to_string())
+            ("key".to_string(), String::new())
         );
         assert_eq!(
             parse_key_value("key=\"\"").unwrap(),
-            ("key".to_string(), "".to_string())
+            ("key".to_string(), String::new())
         );
         assert!(parse_key_value("=value").is_err());
         assert!(parse_key_value("").is_err());
     }
+
+    #[test]
+    fn test_project_relative_source() {
+        // Tests run from the crate's directory, which has a `src`
+        assert_eq!(
+            project_relative_source("src", Path::new("out/proj")),
+            "../../src"
+        );
+        assert_eq!(
+            project_relative_source("./src/../src", Path::new("out/../proj")),
+            "../src"
+        );
+        assert_eq!(project_relative_source("src", Path::new("src")), ".");
+        assert_eq!(
+            project_relative_source("missing", Path::new("out")),
+            "missing"
+        );
+        assert_eq!(
+            project_relative_source("gh://owner/repo", Path::new("out")),
+            "gh://owner/repo"
+        );
+    }
+
+    #[test]
+    fn test_render_changes_collects_errors() {
+        let config = TargetConfig::new("my_project", "./stencil");
+        let stencil = render::tests::stencil(
+            "",
+            &[
+                ("a.txt.jinja", Some("{{ 'a' + 1 }}")),
+                ("b.txt.jinja", Some("{{ project_name }}")),
+                ("c.txt.jinja", Some("{% if %}")),
+            ],
+        );
+        let Err(err) = render_changes(
+            stencil,
+            &config,
+            Path::new("/nonexistent"),
+            &UserConfig::default(),
+        ) else {
+            panic!("expected render errors");
+        };
+        let StencilError::Renders(failures) = &err else {
+            panic!("expected several render errors, got {err}");
+        };
+        let templates: Vec<_> = failures.iter().map(|f| f.template.as_str()).collect();
+        assert_eq!(templates, vec!["a.txt.jinja", "c.txt.jinja"]);
+        assert!(err
+            .to_string()
+            .starts_with("2 files failed to render\n\na.txt.jinja:1:"));
+    }
 }
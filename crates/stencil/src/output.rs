@@ -1,6 +1,9 @@
 // Copyright (c) 2024-2025 David Stanek <dstanek@dstanek.com>
 
-use termcolor::{Color, ColorSpec, WriteColor};
+use std::io::{self, IsTerminal};
+use std::process::{Command, Stdio};
+
+use termcolor::{Ansi, Color, ColorChoice, ColorSpec, NoColor, StandardStream, WriteColor};
 
 use stencil_error::StencilError;
 
@@ -21,3 +24,35 @@ pub fn write_bold(
     stream.reset()?;
     Ok(())
 }
+
+/// Runs `f` with a stream for long output like diffs. The output goes through
+/// the user's pager when one is configured and stdout is a terminal.
+pub fn paged<F>(pager: Option<&str>, color: ColorChoice, f: F) -> Result<(), StencilError>
+where
+    F: FnOnce(&mut dyn WriteColor) -> Result<(), StencilError>,
+{
+    let Some(pager) = pager.filter(|_| io::stdout().is_terminal()) else {
+        let stdout = StandardStream::stdout(color);
+        let mut stdout_lock = stdout.lock();
+        return f(&mut stdout_lock);
+    };
+
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut child = Command::new(shell)
+        .args([flag, pager])
+        .stdin(Stdio::piped())
+        .spawn()?;
+    let stdin = child.stdin.take().unwrap();
+    // The pager's stdin is closed when the writer is dropped
+    let result = if color == ColorChoice::Never {
+        f(&mut NoColor::new(stdin))
+    } else {
+        f(&mut Ansi::new(stdin))
+    };
+    child.wait()?;
+    result
+}
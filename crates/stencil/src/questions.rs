@@ -0,0 +1,186 @@
+// Copyright (c) 2025 David Stanek <dstanek@dstanek.com>
+
+use std::io::{BufRead, Write};
+
+use stencil_error::StencilError;
+use stencil_source::{SourceConfig, SourceQuestion};
+
+use crate::target_config::{Layer, TargetConfig};
+
+/// Questions asked by the stencil that have no answer in the project config.
+pub fn new_questions<'a>(
+    source: &'a SourceConfig,
+    config: &TargetConfig,
+) -> Vec<&'a SourceQuestion> {
+    source
+        .questions
+        .iter()
+        .filter(|q| !config.arguments.contains_key(&q.variable))
+        .collect()
+}
+
+/// Arguments in the project config that the stencil no longer asks for.
+pub fn stale_arguments<'a>(source: &SourceConfig, config: &'a TargetConfig) -> Vec<&'a str> {
+    // A stencil without questions doesn't tell us anything about its arguments
+    if source.questions.is_empty() {
+        return Vec::new();
+    }
+    config
+        .arguments
+        .keys()
+        .filter(|key| source.question(key).is_none())
+        .map(String::as_str)
+        .collect()
+}
+
+/// Answer the given questions. Values passed on the command line win, then
+/// the user is prompted (when `input` is available) and finally the
+/// question's default is used. Each answer comes with the layer it came from.
+pub fn answer(
+    questions: &[&SourceQuestion],
+    provided: &[(String, String)],
+    mut input: Option<(&mut dyn BufRead, &mut dyn Write)>,
+) -> Result<Vec<(String, String, Layer)>, StencilError> {
+    let mut answers = Vec::new();
+    for question in questions {
+        if let Some((_, value)) = provided.iter().find(|(k, _)| *k == question.variable) {
+            answers.push((question.variable.clone(), value.clone(), Layer::Argument));
+            continue;
+        }
+
+        let (value, layer) = if let Some((reader, writer)) = input.as_mut() {
+            (prompt(question, *reader, *writer)?, Layer::Prompt)
+        } else {
+            let default = question.default.clone().ok_or_else(|| {
+                StencilError::ConfigValidation(format!(
+                    "argument '{}' is required by the stencil; pass it with --argument {}=VALUE",
+                    question.variable, question.variable
+                ))
+            })?;
+            (default, Layer::StencilDefault)
+        };
+        answers.push((question.variable.clone(), value, layer));
+    }
+    Ok(answers)
+}
+
+fn prompt(
+    question: &SourceQuestion,
+    reader: &mut dyn BufRead,
+    writer: &mut dyn Write,
+) -> Result<String, StencilError> {
+    match &question.default {
+        Some(default) => write!(writer, "{} [{}]: ", question.question, default)?,
+        None => write!(writer, "{}: ", question.question)?,
+    }
+    writer.flush()?;
+
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(StencilError::Other(format!(
+            "no answer given for argument '{}'",
+            question.variable
+        )));
+    }
+    let line = line.trim();
+    match &question.default {
+        Some(default) if line.is_empty() => Ok(default.clone()),
+        _ => Ok(line.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn source() -> SourceConfig {
+        SourceConfig::parse(
+            r#"
+            [[questions]]
+            variable = "license"
+            question = "Which license?"
+            default = "MIT"
+
+            [[questions]]
+            variable = "python_version"
+            question = "Which Python version?"
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn config(arguments: &[(&str, &str)]) -> TargetConfig {
+        let mut config = TargetConfig::new("my_project", "./src");
+        for (key, value) in arguments {
+            config.arguments.insert((*key).to_string(), (*value).into());
+        }
+        config
+    }
+
+    #[test]
+    fn test_new_and_stale() {
+        let source = source();
+        let config = config(&[("license", "MIT"), ("old", "value")]);
+
+        let new: Vec<&str> = new_questions(&source, &config)
+            .iter()
+            .map(|q| q.variable.as_str())
+            .collect();
+        assert_eq!(new, vec!["python_version"]);
+        assert_eq!(stale_arguments(&source, &config), vec!["old"]);
+        assert!(stale_arguments(&SourceConfig::default(), &config).is_empty());
+    }
+
+    #[test]
+    fn test_answer_without_input() {
+        let source = source();
+        let questions: Vec<&SourceQuestion> = source.questions.iter().collect();
+
+        let provided = vec![("python_version".to_string(), "3.13".to_string())];
+        let answers = answer(&questions, &provided, None).unwrap();
+        assert_eq!(
+            answers,
+            vec![
+                (
+                    "license".to_string(),
+                    "MIT".to_string(),
+                    Layer::StencilDefault
+                ),
+                (
+                    "python_version".to_string(),
+                    "3.13".to_string(),
+                    Layer::Argument
+                ),
+            ]
+        );
+
+        assert!(answer(&questions, &[], None).is_err());
+    }
+
+    #[test]
+    fn test_answer_with_prompt() {
+        let source = source();
+        let questions: Vec<&SourceQuestion> = source.questions.iter().collect();
+
+        let mut reader = Cursor::new("\n3.12\n");
+        let mut writer = Vec::new();
+        let answers = answer(&questions, &[], Some((&mut reader, &mut writer))).unwrap();
+        assert_eq!(
+            answers,
+            vec![
+                ("license".to_string(), "MIT".to_string(), Layer::Prompt),
+                (
+                    "python_version".to_string(),
+                    "3.12".to_string(),
+                    Layer::Prompt
+                ),
+            ]
+        );
+        assert_eq!(
+            String::from_utf8(writer).unwrap(),
+            "Which license? [MIT]: Which Python version?: "
+        );
+    }
+}
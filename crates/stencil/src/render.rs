@@ -1,18 +1,26 @@
 // Copyright (c) 2024-2025 David Stanek <dstanek@dstanek.com>
 
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use chrono::{DateTime, Utc};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::Serialize;
 
 use crate::target_config::TargetConfig;
-use stencil_error::StencilError;
+use crate::user_config::UserConfig;
+use stencil_error::{RenderFailure, StencilError};
 use stencil_rendering::Renderable as RenderableTrait;
-use stencil_rendering::{render, render_str, TemplateVar};
-use stencil_source::{Directory, File, Renderable};
+use stencil_rendering::{RenderError, Renderer, TemplateVar};
+use stencil_source::{
+    parse_bool, Datatype, Directory, File, Renderable, SourceQuestion, Stencil, Undefined,
+};
 
 struct RenderableFile<'a>(&'a File);
 
-#[allow(clippy::needless_lifetimes)]
-impl<'a> RenderableTrait for RenderableFile<'a> {
+impl RenderableTrait for RenderableFile<'_> {
     fn content(&self) -> &str {
         &self.0.content
     }
@@ -20,7 +28,13 @@ impl<'a> RenderableTrait for RenderableFile<'a> {
 
 pub struct RenderingIterator {
     renderables: Vec<Renderable>,
-    variables: HashMap<String, TemplateVar>,
+    renderer: Renderer,
+    template_extensions: Vec<String>,
+    // Paths in the stencil whose contents or names are copied literally
+    literal_contents: GlobSet,
+    literal_paths: GlobSet,
+    // Paths in the stencil left out by a false `when` rule
+    excluded: GlobSet,
     index: usize,
 }
 
@@ -28,58 +42,606 @@ impl Iterator for RenderingIterator {
     type Item = Result<Renderable, StencilError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.index >= self.renderables.len() {
-            return None;
+        while let Some(renderable) = self.renderables.get(self.index) {
+            self.index += 1;
+            // Parts the stencil leaves out are skipped
+            if let Some(result) = self.render(renderable).transpose() {
+                return Some(result);
+            }
+        }
+        None
+    }
+}
+
+impl RenderingIterator {
+    pub fn new(
+        stencil: Stencil,
+        config: &TargetConfig,
+        dest: &Path,
+        user: &UserConfig,
+    ) -> Result<Self, StencilError> {
+        let mut variables = HashMap::from([(
+            "project_name".to_string(),
+            TemplateVar::from(config.project.name.clone()),
+        )]);
+
+        // The user's identity is available to templates unless arguments say otherwise
+        if let Some(author_name) = &user.author_name {
+            variables.insert(
+                "author_name".to_string(),
+                TemplateVar::from(author_name.clone()),
+            );
+        }
+        if let Some(author_email) = &user.author_email {
+            variables.insert(
+                "author_email".to_string(),
+                TemplateVar::from(author_email.clone()),
+            );
         }
 
-        let renderable = &self.renderables[self.index];
-        self.index += 1;
+        // Like the author, but from git, so it matches the project's commits
+        for (variable, key) in [
+            ("git_user_name", "user.name"),
+            ("git_user_email", "user.email"),
+        ] {
+            if let Some(value) = git_config(dest, key) {
+                variables.insert(variable.to_string(), TemplateVar::from(value));
+            }
+        }
+
+        // Secret arguments are only resolved here, right before rendering
+        for (key, value) in config.resolved_arguments()? {
+            let question = stencil.config.question(&key);
+            variables.insert(key, template_var(question, &value));
+        }
+
+        // Computed variables are derived once, before any file is rendered
+        let strict = stencil.config.stencil.undefined == Undefined::Strict;
+        let evaluator = |variables: &HashMap<String, TemplateVar>| {
+            Renderer::new(variables.clone()).with_strict_undefined(strict)
+        };
+        for (key, expression) in &stencil.config.computed {
+            let value = evaluator(&variables).evaluate(expression).map_err(|e| {
+                StencilError::render(&format!("computed variable '{key}'"), &e.to_string())
+            })?;
+            variables.insert(key.clone(), value);
+        }
+
+        // As are the conditions of the `when` rules. A condition that is
+        // undefined is a mistake, even when the stencil is lenient, since it
+        // would silently leave the path out
+        let mut excluded = Vec::new();
+        let evaluator = evaluator(&variables);
+        for (pattern, expression) in &stencil.config.when {
+            let context = format!("when '{pattern}'");
+            let value = evaluator
+                .evaluate_value(expression)
+                .map_err(|e| StencilError::render(&context, &e.to_string()))?;
+            if value.is_undefined() {
+                return Err(StencilError::render(
+                    &context,
+                    &format!("'{expression}' is undefined"),
+                ));
+            }
+            // Arguments without a question are strings, so `false` and the
+            // like are read the way boolean answers are
+            let included = value
+                .as_str()
+                .and_then(parse_bool)
+                .unwrap_or_else(|| value.is_true());
+            if !included {
+                excluded.push(pattern.clone());
+            }
+        }
+        let excluded = glob_set("when", &excluded)?;
+
+        let environment = stencil
+            .config
+            .stencil
+            .environment
+            .iter()
+            .map(|name| (name.clone(), env::var(name).ok()))
+            .collect();
+        let metadata = StencilMetadata {
+            source: stencil.source,
+            reference: stencil.reference,
+            version: stencil.config.stencil.version.clone(),
+        };
+
+        let copy = &stencil.config.copy_without_render;
+        let literal_contents = glob_set("copy_without_render.contents", &copy.contents)?;
+        let literal_paths = glob_set("copy_without_render.paths", &copy.paths)?;
 
+        Ok(Self {
+            template_extensions: stencil.config.template_extensions(),
+            literal_contents,
+            literal_paths,
+            excluded,
+            renderables: stencil.renderables,
+            renderer: Renderer::new(variables)
+                .with_syntax(&stencil.config.syntax)
+                .map_err(|e| StencilError::render("[syntax]", &e.to_string()))?
+                .with_generated(config.generated.clone())
+                .with_templates(stencil.templates)
+                .with_dest(dest)
+                .with_strict_undefined(strict)
+                .with_environment(environment)
+                .with_now(now()?)
+                .with_global("stencil", metadata)
+                .with_global("data", stencil.data),
+            index: 0,
+        })
+    }
+
+    // Renders a file or directory, or returns `None` when the stencil leaves
+    // it out
+    fn render(&self, renderable: &Renderable) -> Result<Option<Renderable>, StencilError> {
         match renderable {
             Renderable::File(file) => {
-                let mut dest_path =
-                    PathBuf::from(render_str(&file.relative_path, &self.variables).unwrap()); // TODO: catch bad rendering (etc...missing variable)
+                if self.is_excluded(&file.relative_path) {
+                    return Ok(None);
+                }
 
-                if dest_path.extension().map_or(true, |ext| ext != "jinja") {
-                    return Some(Ok(Renderable::File(File {
-                        relative_path: dest_path.to_string_lossy().to_string(),
+                // The template extension is dropped before the name is
+                // rendered, so that a name that renders empty is still empty
+                let template = Path::new(&file.relative_path)
+                    .extension()
+                    .is_some_and(|ext| self.template_extensions.iter().any(|t| ext == t.as_str()))
+                    && !self.literal_contents.is_match(&file.relative_path);
+                let Some(relative_path) = self.render_path(&file.relative_path, template)? else {
+                    return Ok(None);
+                };
+                if !template {
+                    return Ok(Some(Renderable::File(File {
+                        relative_path,
                         content: file.content.clone(),
                     })));
                 }
 
                 let rf = RenderableFile(file);
-                let content = render(&rf, &rf, &self.variables).unwrap();
-
-                dest_path.set_extension("");
-                Some(Ok(Renderable::File(File {
-                    relative_path: dest_path.to_string_lossy().to_string(),
+                let content = self.renderer.render(&rf, &rf).map_err(|e| {
+                    let compiled = self.renderer.compiled(&file.content);
+                    self.error(&file.relative_path, &compiled, &e)
+                })?;
+                Ok(Some(Renderable::File(File {
+                    relative_path,
                     content,
                 })))
             }
             Renderable::Directory(directory) => {
-                Some(Ok(Renderable::Directory(Directory {
-                    relative_path: render_str(&directory.relative_path, &self.variables).unwrap(), // TODO: catch bad rendering (etc...missing variable)
-                })))
+                if self.is_excluded(&directory.relative_path) {
+                    return Ok(None);
+                }
+                Ok(self
+                    .render_path(&directory.relative_path, false)?
+                    .map(|relative_path| Renderable::Directory(Directory { relative_path })))
+            }
+        }
+    }
+
+    // A path is excluded along with everything in it, so a rule for a
+    // directory covers its contents
+    fn is_excluded(&self, path: &str) -> bool {
+        Path::new(path)
+            .ancestors()
+            .any(|ancestor| self.excluded.is_match(ancestor))
+    }
+
+    // File and directory names are templates too, unless they are literal.
+    // Each name in the path is rendered on its own, so a literal directory
+    // keeps its name while the names of its contents are still rendered. A
+    // name that renders empty, like `{% if use_docker %}Dockerfile{% endif %}`,
+    // leaves out the path and, since they share it, everything in it. The
+    // extension of a template is dropped from its name first
+    fn render_path(&self, path: &str, template: bool) -> Result<Option<String>, StencilError> {
+        let mut stencil_path = PathBuf::new();
+        let mut names = Vec::new();
+        let mut segments = path.split('/').peekable();
+        while let Some(segment) = segments.next() {
+            stencil_path.push(segment);
+            let name = match segment.rsplit_once('.') {
+                Some((stem, _)) if template && segments.peek().is_none() => stem,
+                _ => segment,
+            };
+            let rendered = if self.literal_paths.is_match(&stencil_path) {
+                name.to_string()
+            } else {
+                self.renderer
+                    .render_str(name)
+                    .map_err(|e| self.error(path, name, &e))?
+            };
+            if rendered.split('/').any(|segment| segment.trim().is_empty()) {
+                return Ok(None);
             }
+            names.push(rendered);
+        }
+        Ok(Some(names.join("/")))
+    }
+
+    // Describes a render error with where it happened and a snippet of the
+    // template around it. `source` is what the renderer compiled, which the
+    // error's location points into
+    fn error(&self, path: &str, source: &str, error: &RenderError) -> StencilError {
+        let RenderError::TemplateError(e) = error else {
+            return StencilError::render(path, &error.to_string());
+        };
+        let mut e = e;
+
+        // Errors in an included template point into that template
+        while let Some(cause) = std::error::Error::source(e)
+            .and_then(|cause| cause.downcast_ref::<minijinja::Error>())
+            .filter(|cause| cause.line().is_some())
+        {
+            e = cause;
         }
+        let included = e
+            .name()
+            .and_then(|name| Some((name, self.renderer.template(name)?)));
+        let (template, source) = match &included {
+            Some((name, included)) => (format!("{name} (included from {path})"), included.as_str()),
+            None => (path.to_string(), source),
+        };
+        let message = match e.detail() {
+            Some(detail) => format!("{}: {detail}", e.kind()),
+            None => e.kind().to_string(),
+        };
+        let Some(line) = e.line() else {
+            return StencilError::render(&template, &message);
+        };
+
+        let snippet = source.lines().nth(line.saturating_sub(1)).unwrap_or("");
+        let line_start = source
+            .lines()
+            .take(line.saturating_sub(1))
+            .map(|l| l.len() + 1)
+            .sum::<usize>();
+        // The range is only used when it falls within the reported line
+        let span = e.range().and_then(|range| {
+            let before = source.get(line_start..range.start)?;
+            let spanned = source.get(range)?;
+            (!before.contains('\n') && !spanned.contains('\n'))
+                .then(|| (before.chars().count() + 1, spanned.chars().count().max(1)))
+        });
+
+        StencilError::Render(RenderFailure {
+            template,
+            message,
+            line: Some(line),
+            column: span.map(|(column, _)| column),
+            width: span.map_or(0, |(_, width)| width),
+            snippet: Some(snippet.to_string()),
+        })
+    }
+
+    /// The generated values after rendering, including any new ones.
+    pub fn generated(&self) -> BTreeMap<String, String> {
+        self.renderer.generated()
     }
 }
 
-impl RenderingIterator {
-    pub fn new(renderables: Vec<Renderable>, config: &TargetConfig) -> Self {
-        let mut variables = HashMap::from([(
-            "project_name".to_string(),
-            TemplateVar::from(config.project.name.clone()),
-        )]);
+/// What templates see as `stencil`.
+#[derive(Serialize)]
+struct StencilMetadata {
+    source: String,
+    #[serde(rename = "ref")]
+    reference: Option<String>,
+    version: String,
+}
 
-        for (key, value) in &config.arguments {
-            variables.insert(key.clone(), TemplateVar::from(value.clone()));
+fn glob_set(setting: &str, patterns: &[String]) -> Result<GlobSet, StencilError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| StencilError::ConfigValidation(format!("{setting}: {e}")))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| StencilError::ConfigValidation(format!("{setting}: {e}")))
+}
+
+// The render time, or `SOURCE_DATE_EPOCH` so that builds are reproducible
+fn now() -> Result<DateTime<Utc>, StencilError> {
+    match env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => epoch
+            .trim()
+            .parse()
+            .ok()
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+            .ok_or_else(|| {
+                StencilError::render(
+                    "SOURCE_DATE_EPOCH",
+                    &format!("must be a number of seconds, not '{epoch}'"),
+                )
+            }),
+        Err(_) => Ok(Utc::now()),
+    }
+}
+
+// A value from the git config of the destination, or from the global config
+// when the destination doesn't exist yet, if git is available
+fn git_config(dest: &Path, key: &str) -> Option<String> {
+    let mut command = Command::new("git");
+    if dest.is_dir() {
+        command.arg("-C").arg(dest).args(["config", "--get", key]);
+    } else {
+        command.args(["config", "--global", "--get", key]);
+    }
+    let output = command.output().ok()?;
+    let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !value.is_empty()).then_some(value)
+}
+
+// Arguments are stored as strings; the stencil's questions say how templates see them
+fn template_var(question: Option<&SourceQuestion>, value: &str) -> TemplateVar {
+    match question.map(|q| q.datatype) {
+        Some(Datatype::Int) => value
+            .parse::<i64>()
+            .map_or_else(|_| TemplateVar::from(value), TemplateVar::Int),
+        Some(Datatype::Bool) => {
+            parse_bool(value).map_or_else(|| TemplateVar::from(value), TemplateVar::Bool)
         }
+        _ => TemplateVar::from(value),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use stencil_source::SourceConfig;
 
-        Self {
+    // A stencil held in memory. Paths without contents are directories, and
+    // partials are only templates, as when a stencil is loaded
+    pub(crate) fn stencil(manifest: &str, paths: &[(&str, Option<&str>)]) -> Stencil {
+        let mut renderables = Vec::new();
+        let mut templates = BTreeMap::new();
+        for (path, content) in paths {
+            let path = (*path).to_string();
+            if let Some(content) = content {
+                templates.insert(path.clone(), (*content).to_string());
+            }
+            if path.starts_with("_partials/") {
+                continue;
+            }
+            renderables.push(match content {
+                Some(content) => Renderable::File(File::new(path, (*content).to_string())),
+                None => Renderable::Directory(Directory::new(path)),
+            });
+        }
+        Stencil {
+            config: SourceConfig::parse(manifest).unwrap(),
             renderables,
-            variables,
-            index: 0,
+            templates,
+            data: BTreeMap::new(),
+            source: "./stencil".to_string(),
+            reference: None,
+        }
+    }
+
+    fn iterator(stencil: Stencil) -> Result<RenderingIterator, StencilError> {
+        let config = TargetConfig::new("my_project", "./stencil");
+        RenderingIterator::new(
+            stencil,
+            &config,
+            Path::new("/nonexistent"),
+            &UserConfig::default(),
+        )
+    }
+
+    // The paths the stencil renders, with the contents of files
+    fn rendered(stencil: Stencil) -> Vec<(String, Option<String>)> {
+        iterator(stencil)
+            .unwrap()
+            .map(|result| match result.unwrap() {
+                Renderable::File(file) => (file.relative_path, Some(file.content)),
+                Renderable::Directory(directory) => (directory.relative_path, None),
+            })
+            .collect()
+    }
+
+    fn failure(stencil: Stencil) -> String {
+        let mut results = iterator(stencil).unwrap();
+        match results.next() {
+            Some(Err(e)) => e.to_string(),
+            _ => panic!("expected a render error"),
+        }
+    }
+
+    #[test]
+    fn test_error_snippet() {
+        let observed = failure(stencil(
+            "",
+            &[("main.txt.jinja", Some("first\n{{ 'a' + 1 }}\n"))],
+        ));
+        assert_eq!(
+            observed,
+            "Rendering error: main.txt.jinja:2:4: invalid operation: tried to use + operator on unsupported types string and number\n  |\n2 | {{ 'a' + 1 }}\n  |    ^^^^^^^"
+        );
+    }
+
+    #[test]
+    fn test_error_snippet_after_user_content() {
+        let observed = failure(stencil(
+            "",
+            &[(
+                "main.py.jinja",
+                Some("    {{ user_content('main', '#') }}\n{{ 'a' + 1 }}\n"),
+            )],
+        ));
+        assert_eq!(
+            observed,
+            "Rendering error: main.py.jinja:2:4: invalid operation: tried to use + operator on unsupported types string and number\n  |\n2 | {{ 'a' + 1 }}\n  |    ^^^^^^^"
+        );
+    }
+
+    #[test]
+    fn test_error_in_included_template() {
+        let observed = failure(stencil(
+            "",
+            &[
+                ("_partials/bad.jinja", Some("ok\n{{ 'a' + 1 }}\n")),
+                (
+                    "main.txt.jinja",
+                    Some("{% include '_partials/bad.jinja' %}"),
+                ),
+            ],
+        ));
+        assert_eq!(
+            observed,
+            "Rendering error: _partials/bad.jinja (included from main.txt.jinja):2:4: invalid operation: tried to use + operator on unsupported types string and number\n  |\n2 | {{ 'a' + 1 }}\n  |    ^^^^^^^"
+        );
+    }
+
+    #[test]
+    fn test_computed_undefined() {
+        let manifest = "[computed]\nslug = \"projct_name\"";
+        let err = iterator(stencil(manifest, &[])).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Rendering error: computed variable 'slug': \
+             Template error: undefined value: 'projct_name' is undefined"
+        );
+
+        let lenient = format!("[stencil]\nundefined = \"lenient\"\n{manifest}");
+        assert!(iterator(stencil(&lenient, &[])).is_ok());
+    }
+
+    #[test]
+    fn test_literal_paths() {
+        let manifest = "[copy_without_render]\npaths = ['\\{\\{ cookiecutter.name \\}\\}']";
+        let observed = rendered(stencil(
+            manifest,
+            &[
+                ("{{ cookiecutter.name }}", None),
+                ("{{ cookiecutter.name }}/{{ project_name }}.txt", Some("x")),
+            ],
+        ));
+        assert_eq!(
+            observed,
+            vec![
+                ("{{ cookiecutter.name }}".to_string(), None),
+                (
+                    "{{ cookiecutter.name }}/my_project.txt".to_string(),
+                    Some("x".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_template_extensions() {
+        let manifest = "[stencil]\ntemplate_extensions = ['.j2']\n\
+                        [copy_without_render]\ncontents = ['raw/**']";
+        let observed = rendered(stencil(
+            manifest,
+            &[
+                ("a.txt.j2", Some("{{ project_name }}")),
+                ("b.txt.jinja", Some("{{ project_name }}")),
+                ("raw/c.txt.j2", Some("{{ project_name }}")),
+            ],
+        ));
+        assert_eq!(
+            observed,
+            vec![
+                ("a.txt".to_string(), Some("my_project".to_string())),
+                (
+                    "b.txt.jinja".to_string(),
+                    Some("{{ project_name }}".to_string())
+                ),
+                (
+                    "raw/c.txt.j2".to_string(),
+                    Some("{{ project_name }}".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_names() {
+        let paths = [
+            (
+                "{% if use_docker %}Dockerfile{% endif %}.jinja",
+                Some("FROM rust"),
+            ),
+            ("{% if use_docker %}docker{% endif %}", None),
+            (
+                "{% if use_docker %}docker{% endif %}/entrypoint.sh",
+                Some("#!/bin/sh"),
+            ),
+            ("README.md.jinja", Some("# {{ project_name }}")),
+        ];
+
+        let observed = rendered(stencil("[computed]\nuse_docker = \"false\"", &paths));
+        assert_eq!(
+            observed,
+            vec![("README.md".to_string(), Some("# my_project".to_string()))]
+        );
+
+        let observed = rendered(stencil("[computed]\nuse_docker = \"true\"", &paths));
+        assert_eq!(
+            observed,
+            vec![
+                ("Dockerfile".to_string(), Some("FROM rust".to_string())),
+                ("docker".to_string(), None),
+                (
+                    "docker/entrypoint.sh".to_string(),
+                    Some("#!/bin/sh".to_string())
+                ),
+                ("README.md".to_string(), Some("# my_project".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_when() {
+        let manifest = r#"
+            [computed]
+            use_docker = "false"
+
+            [when]
+            "docker" = "use_docker"
+            "none.txt" = "none"
+            "empty.txt" = "[]"
+            "false.txt" = "'false'"
+            "kept.txt" = "project_name"
+        "#;
+        let observed = rendered(stencil(
+            manifest,
+            &[
+                ("docker", None),
+                ("docker/Dockerfile", Some("FROM rust")),
+                ("docker/scripts", None),
+                ("docker/scripts/entrypoint.sh", Some("#!/bin/sh")),
+                ("none.txt", Some("")),
+                ("empty.txt", Some("")),
+                ("false.txt", Some("")),
+                ("kept.txt", Some("")),
+            ],
+        ));
+        assert_eq!(
+            observed,
+            vec![("kept.txt".to_string(), Some(String::new()))]
+        );
+    }
+
+    #[test]
+    fn test_when_undefined() {
+        for undefined in ["strict", "lenient"] {
+            let manifest = format!(
+                "[stencil]\nundefined = \"{undefined}\"\n[when]\n\"docker\" = \"use_dokcer\""
+            );
+            let err = iterator(stencil(&manifest, &[])).err().unwrap();
+            assert!(
+                err.to_string()
+                    .starts_with("Rendering error: when 'docker': "),
+                "{err}"
+            );
+            assert!(
+                err.to_string().contains("'use_dokcer' is undefined"),
+                "{err}"
+            );
         }
     }
 }
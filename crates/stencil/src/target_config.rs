@@ -1,19 +1,97 @@
 // Copyright (c) 2024-2025 David Stanek <dstanek@dstanek.com>
 
 use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 
 use serde::Deserialize;
 use serde::Serialize;
+use toml_edit::{DocumentMut, Item, Table};
 
 use stencil_error::StencilError;
+use stencil_source::SourceConfig;
+
+use crate::interpolation;
+use crate::questions;
+
+/// The name of the project config file when it isn't embedded in a package
+/// manifest.
+pub const CONFIG_FILENAME: &str = ".stencil.toml";
+
+/// The newest config schema this binary understands. It is the version
+/// written on every save.
+pub const SCHEMA_VERSION: u32 = 1;
+
+type Migration = fn(&mut toml::Table);
+
+/// Upgrades the raw config from each older schema version to the next one.
+/// They run in order before the config is deserialized.
+const MIGRATIONS: &[(u32, Migration)] = &[(0, migrate_v0)];
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct TargetConfig {
     pub stencil: ConfigStencil,
     pub project: ConfigProject,
-    pub arguments: BTreeMap<String, String>,
+    pub arguments: BTreeMap<String, ArgumentValue>,
+    /// Values created by the stencil's `generated(...)` calls, kept so that
+    /// later renders reuse them.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub generated: BTreeMap<String, String>,
+    /// Named sets of arguments laid over the base arguments with `--profile`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+    /// The layer each value came from, keyed by its dotted name.
+    #[serde(skip)]
+    pub origins: BTreeMap<String, Layer>,
+    /// The schema version the file was written with, when it was older than
+    /// [`SCHEMA_VERSION`] and had to be migrated on load.
+    #[serde(skip)]
+    pub migrated_from: Option<u32>,
+    /// The profile applied on top of the config file, if any.
+    #[serde(skip)]
+    pub active_profile: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Profile {
+    pub arguments: BTreeMap<String, ArgumentValue>,
+    /// A subdirectory of the project to render into instead of its root.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dest: Option<String>,
+}
+
+/// Where a config value came from. Later layers take precedence over
+/// earlier ones: stencil defaults < user config < `.stencil.toml` <
+/// `--profile` < environment variables < `--override`. `Argument` and
+/// `Prompt` are answers to questions the config doesn't answer yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Layer {
+    StencilDefault,
+    UserConfig,
+    ConfigFile,
+    Argument,
+    Prompt,
+    Profile(String),
+    Environment(String),
+    Override,
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Layer::StencilDefault => write!(f, "stencil default"),
+            Layer::UserConfig => write!(f, "user config"),
+            Layer::ConfigFile => write!(f, "config file"),
+            Layer::Argument => write!(f, "--argument"),
+            Layer::Prompt => write!(f, "prompt"),
+            Layer::Profile(name) => write!(f, "profile: {name}"),
+            Layer::Environment(name) => write!(f, "environment: {name}"),
+            Layer::Override => write!(f, "--override"),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -27,7 +105,91 @@ pub struct ConfigProject {
     pub src: String, // TODO: should this be a Path?
 }
 
+/// An argument's value, either given literally or read from a secret source
+/// at render time. Secret values are never written to the config file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ArgumentValue {
+    Literal(String),
+    Env { env: String },
+    File { file: String },
+}
+
+impl ArgumentValue {
+    pub fn is_secret(&self) -> bool {
+        !matches!(self, ArgumentValue::Literal(_))
+    }
+
+    pub fn resolve(&self) -> Result<String, StencilError> {
+        match self {
+            ArgumentValue::Literal(value) => Ok(value.clone()),
+            ArgumentValue::Env { env } => env::var(env).map_err(|_| {
+                StencilError::ConfigValidation(format!("environment variable '{env}' is not set"))
+            }),
+            ArgumentValue::File { file } => {
+                let path = expand_home(file);
+                let contents = fs::read_to_string(&path).map_err(|e| {
+                    StencilError::ConfigValidation(format!("reading '{}': {e}", path.display()))
+                })?;
+                Ok(contents.trim_end_matches(['\r', '\n']).to_string())
+            }
+        }
+    }
+}
+
+impl From<String> for ArgumentValue {
+    fn from(value: String) -> Self {
+        ArgumentValue::Literal(value)
+    }
+}
+
+impl From<&str> for ArgumentValue {
+    fn from(value: &str) -> Self {
+        ArgumentValue::Literal(value.to_string())
+    }
+}
+
+impl fmt::Display for ArgumentValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgumentValue::Literal(value) => write!(f, "{value}"),
+            ArgumentValue::Env { env } => write!(f, "******** (from env {env})"),
+            ArgumentValue::File { file } => write!(f, "******** (from file {file})"),
+        }
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
 impl TargetConfig {
+    pub fn new(name: &str, src: &str) -> Self {
+        let mut config = TargetConfig {
+            stencil: ConfigStencil {
+                version: SCHEMA_VERSION.to_string(),
+            },
+            project: ConfigProject {
+                name: name.to_string(),
+                src: src.to_string(),
+            },
+            arguments: BTreeMap::new(),
+            generated: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            origins: BTreeMap::new(),
+            migrated_from: None,
+            active_profile: None,
+        };
+        // These are the values a new config file is written with
+        for key in ["stencil.version", "project.name", "project.src"] {
+            config.origins.insert(key.to_string(), Layer::ConfigFile);
+        }
+        config
+    }
+
     fn validate(&self) -> Result<(), StencilError> {
         if self.stencil.version.is_empty() {
             return Err(StencilError::ConfigValidation(
@@ -47,6 +209,214 @@ impl TargetConfig {
         Ok(())
     }
 
+    /// The argument values with every secret source read.
+    pub fn resolved_arguments(&self) -> Result<BTreeMap<String, String>, StencilError> {
+        self.arguments
+            .iter()
+            .map(|(key, value)| {
+                let resolved = value
+                    .resolve()
+                    .map_err(|e| StencilError::ConfigValidation(format!("arguments.{key}: {e}")))?;
+                Ok((key.clone(), resolved))
+            })
+            .collect()
+    }
+
+    /// The resolved values of secret arguments, which must never be shown.
+    pub fn secrets(&self) -> Vec<String> {
+        self.arguments
+            .values()
+            .filter(|value| value.is_secret())
+            .filter_map(|value| value.resolve().ok())
+            .filter(|value| !value.is_empty())
+            .collect()
+    }
+
+    /// Validates the arguments against the stencil's questions. Every
+    /// violation is reported together in a single error.
+    pub fn validate_arguments(&self, source: &SourceConfig) -> Result<(), StencilError> {
+        let arguments = self.resolved_arguments()?;
+        let mut violations = Vec::new();
+        for question in &source.questions {
+            match arguments.get(&question.variable) {
+                Some(value) => {
+                    if let Err(expected) = question.check(value) {
+                        violations.push(format!("arguments.{}: {expected}", question.variable));
+                    }
+                }
+                None if question.required => {
+                    violations.push(format!(
+                        "arguments.{}: a value is required",
+                        question.variable
+                    ));
+                }
+                None => {}
+            }
+        }
+        // A misspelled argument would otherwise render as an empty string.
+        // Others are left over from an older stencil and are only warned about
+        for key in questions::stale_arguments(source, self) {
+            if closest_question(key, source).is_some() {
+                violations.push(format!(
+                    "{} If it is no longer used, remove it with \
+                     'stencil config unset arguments.{key}'",
+                    unknown_argument(key, source)
+                ));
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(StencilError::ConfigValidation(format!(
+                "invalid arguments:\n  {}",
+                violations.join("\n  ")
+            )))
+        }
+    }
+
+    /// Sets a value by its dotted key, recording the layer it came from.
+    pub fn set(&mut self, key: &str, value: String, layer: Layer) -> Result<(), StencilError> {
+        match key {
+            "stencil.version" => self.stencil.version = value,
+            "project.name" => self.project.name = value,
+            "project.src" => self.project.src = value,
+            _ => match key.strip_prefix("arguments.") {
+                Some(arg_key) if !arg_key.is_empty() => {
+                    self.arguments.insert(arg_key.to_string(), value.into());
+                }
+                _ => {
+                    return Err(StencilError::ConfigValidation(format!(
+                        "unknown key '{key}'"
+                    )))
+                }
+            },
+        }
+        self.origins.insert(key.to_string(), layer);
+        Ok(())
+    }
+
+    /// The value of a dotted key, with secrets redacted.
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.explain()
+            .into_iter()
+            .find(|(k, _, _)| k == key)
+            .map(|(_, value, _)| value)
+    }
+
+    /// Removes an argument. The stencil and project settings are required so
+    /// they can only be changed, not removed.
+    pub fn unset(&mut self, key: &str) -> Result<(), StencilError> {
+        match key.strip_prefix("arguments.") {
+            Some(arg_key) if self.arguments.remove(arg_key).is_some() => {
+                self.origins.remove(key);
+                Ok(())
+            }
+            Some(_) => Err(StencilError::ConfigValidation(format!(
+                "'{key}' is not set"
+            ))),
+            None if self.get(key).is_some() => Err(StencilError::ConfigValidation(format!(
+                "'{key}' is required and can't be unset"
+            ))),
+            None => Err(StencilError::ConfigValidation(format!(
+                "unknown key '{key}'"
+            ))),
+        }
+    }
+
+    /// Lays a profile's arguments over the base arguments.
+    pub fn apply_profile(&mut self, name: &str) -> Result<(), StencilError> {
+        let Some(profile) = self.profiles.get(name) else {
+            let known = self.profiles.keys().cloned().collect::<Vec<_>>();
+            return Err(StencilError::ConfigValidation(if known.is_empty() {
+                format!("unknown profile '{name}', the config has no profiles")
+            } else {
+                format!(
+                    "unknown profile '{name}', expected one of {}",
+                    known.join(", ")
+                )
+            }));
+        };
+        for (key, value) in &profile.arguments {
+            self.arguments.insert(key.clone(), value.clone());
+            self.origins
+                .insert(format!("arguments.{key}"), Layer::Profile(name.to_string()));
+        }
+        self.active_profile = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Resolves `{{ key }}` references to other config values and `{{ env.NAME }}`
+    /// references to environment variables in the project settings and
+    /// literal arguments.
+    pub fn interpolate<F>(&mut self, env: F) -> Result<(), StencilError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut values = BTreeMap::from([
+            ("project.name".to_string(), Some(self.project.name.clone())),
+            ("project.src".to_string(), Some(self.project.src.clone())),
+        ]);
+        for (key, value) in &self.arguments {
+            let value = match value {
+                ArgumentValue::Literal(value) => Some(value.clone()),
+                _ => None,
+            };
+            values.insert(format!("arguments.{key}"), value);
+        }
+
+        for (key, value) in interpolation::interpolate(&values, env)? {
+            match key.as_str() {
+                "project.name" => self.project.name = value,
+                "project.src" => self.project.src = value,
+                _ => {
+                    let name = key.strip_prefix("arguments.").unwrap();
+                    self.arguments.insert(name.to_string(), value.into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The directory to render into, which the active profile may move to a
+    /// subdirectory of the project root. A profile can't send the output
+    /// outside of the project.
+    pub fn output_dir(&self, root: &Path) -> Result<PathBuf, StencilError> {
+        let Some((name, dest)) = self.active_profile.as_ref().and_then(|name| {
+            let dest = self.profiles.get(name)?.dest.as_ref()?;
+            Some((name, dest))
+        }) else {
+            return Ok(root.to_path_buf());
+        };
+        let relative = Path::new(dest);
+        let inside = relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+        if dest.is_empty() || !inside {
+            return Err(StencilError::ConfigValidation(format!(
+                "profiles.{name}.dest: '{dest}' must be a relative path inside the project"
+            )));
+        }
+        Ok(root.join(relative))
+    }
+
+    /// Applies `STENCIL_PROJECT_SRC` and `STENCIL_ARG_<NAME>` environment
+    /// variables. Argument names are lowercased.
+    pub fn apply_environment<I>(&mut self, vars: I) -> Result<(), StencilError>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        for (name, value) in vars {
+            let key = match name.strip_prefix("STENCIL_ARG_") {
+                Some(arg) if !arg.is_empty() => format!("arguments.{}", arg.to_lowercase()),
+                _ if name == "STENCIL_PROJECT_SRC" => "project.src".to_string(),
+                _ => continue,
+            };
+            self.set(&key, value, Layer::Environment(name))?;
+        }
+        Ok(())
+    }
+
     pub fn apply_overrides(&mut self, overrides: Vec<String>) -> Result<(), StencilError> {
         let mut override_map: HashMap<String, String> = HashMap::new();
         for override_str in overrides {
@@ -58,30 +428,74 @@ impl TargetConfig {
         }
 
         for (key, value) in override_map {
-            match key.as_str() {
-                "stencil.version" => self.stencil.version = value,
-                "project.name" => self.project.name = value,
-                "project.src" => self.project.src = value,
-                _ => {
-                    if key.starts_with("arguments.") {
-                        let arg_key = key.trim_start_matches("arguments.").to_string();
-                        self.arguments.insert(arg_key, value);
-                    } else {
-                        eprintln!("Unknown override key: {key}");
+            self.set(&key, value, Layer::Override)
+                .map_err(|e| match e {
+                    StencilError::ConfigValidation(message) => {
+                        StencilError::ConfigOverride(message)
                     }
-                }
-            }
+                    e => e,
+                })?;
         }
 
         Ok(())
     }
 
+    /// Each value with the layer it came from. Secret arguments are redacted.
+    /// The layer is `None` for a value that was set without recording one.
+    pub fn explain(&self) -> Vec<(String, String, Option<Layer>)> {
+        let mut values = vec![
+            ("stencil.version".to_string(), self.stencil.version.clone()),
+            ("project.name".to_string(), self.project.name.clone()),
+            ("project.src".to_string(), self.project.src.clone()),
+        ];
+        for (key, value) in &self.arguments {
+            values.push((format!("arguments.{key}"), value.to_string()));
+        }
+        values
+            .into_iter()
+            .map(|(key, value)| {
+                let layer = self.origins.get(&key).cloned();
+                (key, value, layer)
+            })
+            .collect()
+    }
+
+    /// Writes the config as an edit of the existing file, so comments,
+    /// ordering and keys that stencil doesn't know about are kept.
     pub fn save(&self, path: &PathBuf) -> Result<(), StencilError> {
         let contents = match toml::to_string(self) {
             Ok(contents) => contents,
             Err(e) => return Err(StencilError::from(e)),
         };
-        fs::write(path, contents)?;
+        let config = parse_document(path, &contents)?;
+        let mut document = if path.exists() {
+            parse_document(path, &fs::read_to_string(path)?)?
+        } else {
+            DocumentMut::new()
+        };
+        let separate = !document.is_empty();
+        let end = last_position(document.as_table()).map_or(0, |p| p + 1);
+
+        let mut table = document.as_table_mut();
+        for key in embedded_keys(path) {
+            table = table
+                .entry(key)
+                .or_insert_with(|| {
+                    let mut parent = Table::new();
+                    parent.set_implicit(true);
+                    parent.set_position(end);
+                    Item::Table(parent)
+                })
+                .as_table_mut()
+                .ok_or_else(|| {
+                    StencilError::ConfigValidation(format!(
+                        "{}: '{key}' is not a table",
+                        path.display()
+                    ))
+                })?;
+        }
+        merge(table, config.as_table(), &[], separate);
+        fs::write(path, document.to_string())?;
         Ok(())
     }
 
@@ -90,13 +504,641 @@ impl TargetConfig {
             Ok(contents) => contents,
             Err(e) => return Err(StencilError::from(e)),
         };
-        let config: Self = match toml::from_str(contents.as_str()) {
+        let mut table: toml::Table = match toml::from_str(contents.as_str()) {
+            Ok(table) => table,
+            Err(e) => return Err(StencilError::from(e)),
+        };
+        let keys = embedded_keys(Path::new(path));
+        for key in keys {
+            table = match table.remove(*key) {
+                Some(toml::Value::Table(table)) => table,
+                _ => {
+                    return Err(StencilError::ConfigValidation(format!(
+                        "{path} has no [{}] table",
+                        keys.join(".")
+                    )))
+                }
+            };
+        }
+        let version = migrate(&mut table)?;
+        let mut config: Self = match toml::Value::Table(table).try_into() {
             Ok(config) => config,
             Err(e) => return Err(StencilError::from(e)),
         };
+        if version < SCHEMA_VERSION {
+            config.migrated_from = Some(version);
+        }
+        for (key, _, _) in config.explain() {
+            config.origins.insert(key, Layer::ConfigFile);
+        }
         match config.validate() {
             Ok(()) => Ok(config),
             Err(e) => Err(e),
         }
     }
 }
+
+/// Brings a raw config up to [`SCHEMA_VERSION`], returning the version it
+/// was written with.
+fn migrate(table: &mut toml::Table) -> Result<u32, StencilError> {
+    let version = match table.get("stencil").and_then(|s| s.get("version")) {
+        None => 0,
+        Some(toml::Value::String(version)) => version.parse().map_err(|_| {
+            StencilError::ConfigValidation(format!("invalid stencil.version '{version}'"))
+        })?,
+        Some(version) => {
+            return Err(StencilError::ConfigValidation(format!(
+                "invalid stencil.version {version}, expected a string"
+            )))
+        }
+    };
+    if version > SCHEMA_VERSION {
+        return Err(StencilError::ConfigValidation(format!(
+            "the config uses schema version {version} but this stencil only supports up to \
+             version {SCHEMA_VERSION}; upgrade stencil to use it"
+        )));
+    }
+
+    for (from, migration) in MIGRATIONS {
+        if *from >= version {
+            migration(table);
+            let stencil = table
+                .entry("stencil")
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if let Some(stencil) = stencil.as_table_mut() {
+                stencil.insert("version".to_string(), (from + 1).to_string().into());
+            }
+        }
+    }
+    Ok(version)
+}
+
+// Configs written before the [stencil] table existed could also leave out
+// an empty [arguments] table
+fn migrate_v0(table: &mut toml::Table) {
+    table
+        .entry("arguments")
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+}
+
+/// Looks for the project config in `dir`. A `.stencil.toml` wins, otherwise
+/// the config may be embedded in `Cargo.toml` or `pyproject.toml`.
+pub fn discover(dir: &Path) -> Option<PathBuf> {
+    let path = dir.join(CONFIG_FILENAME);
+    if path.exists() {
+        return Some(path);
+    }
+    ["Cargo.toml", "pyproject.toml"]
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| {
+            let Ok(contents) = fs::read_to_string(path) else {
+                return false;
+            };
+            let Ok(mut table) = toml::from_str::<toml::Table>(&contents) else {
+                return false;
+            };
+            for key in embedded_keys(path) {
+                table = match table.remove(*key) {
+                    Some(toml::Value::Table(table)) => table,
+                    _ => return false,
+                };
+            }
+            true
+        })
+}
+
+/// Finds the config of the project containing `dir` by looking in `dir` and
+/// then each of its parents. Returns the config and the project's root.
+pub fn find_project(dir: &Path) -> Option<(PathBuf, PathBuf)> {
+    if let Some(path) = discover(dir) {
+        return Some((path, dir.to_path_buf()));
+    }
+    let absolute = std::path::absolute(dir).ok()?;
+    absolute
+        .ancestors()
+        .skip(1)
+        .find_map(|root| Some((discover(root)?, root.to_path_buf())))
+}
+
+/// The table holding the config when it is embedded in a package manifest,
+/// or no keys when the file is a stencil config of its own.
+fn embedded_keys(path: &Path) -> &'static [&'static str] {
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some("Cargo.toml") => &["package", "metadata", "stencil"],
+        Some("pyproject.toml") => &["tool", "stencil"],
+        _ => &[],
+    }
+}
+
+fn parse_document(path: &Path, contents: &str) -> Result<DocumentMut, StencilError> {
+    contents
+        .parse()
+        .map_err(|e| StencilError::ConfigValidation(format!("{}: {e}", path.display())))
+}
+
+fn last_position(table: &Table) -> Option<usize> {
+    table
+        .iter()
+        .filter_map(|(_, item)| item.as_table())
+        .filter_map(|t| t.position().max(last_position(t)))
+        .max()
+}
+
+/// Whether `save` may remove `key` from the table at `path` when the config
+/// no longer has it. Anything else missing from the config was added by hand.
+fn owned(path: &[&str], key: &str) -> bool {
+    match path {
+        [] => ["stencil", "project", "arguments", "generated", "profiles"].contains(&key),
+        ["arguments" | "generated" | "profiles"] | ["profiles", _, "arguments"] => true,
+        ["profiles", _] => key == "arguments" || key == "dest",
+        _ => false,
+    }
+}
+
+// Edits `existing` to match `new` while keeping its formatting and comments
+fn merge(existing: &mut Table, new: &Table, path: &[&str], separate: bool) {
+    existing.retain(|key, _| new.contains_key(key) || !owned(path, key));
+
+    // toml_edit orders tables by their position in the document, so new
+    // tables go after the last one already in this table
+    let position = last_position(existing).or(existing.position()).unwrap_or(0);
+
+    for (key, item) in new {
+        let child_path = [path, &[key]].concat();
+        match (existing.get_mut(key), item) {
+            (Some(Item::Table(old)), Item::Table(new)) => {
+                merge(old, new, &child_path, separate);
+            }
+            (Some(Item::Value(old)), Item::Value(new)) => {
+                if !same_value(old, new) {
+                    let decor = old.decor().clone();
+                    *old = new.clone();
+                    *old.decor_mut() = decor;
+                }
+            }
+            _ => {
+                let mut item = item.clone();
+                if let Item::Table(table) = &mut item {
+                    set_position(table, position);
+                    if separate {
+                        table.decor_mut().set_prefix("\n");
+                    }
+                }
+                existing.insert(key, item);
+            }
+        }
+    }
+}
+
+fn same_value(a: &toml_edit::Value, b: &toml_edit::Value) -> bool {
+    let parse = |v: &toml_edit::Value| toml::from_str::<toml::Table>(&format!("v = {v}")).ok();
+    parse(a) == parse(b)
+}
+
+fn set_position(table: &mut Table, position: usize) {
+    table.set_position(position);
+    for (_, item) in table.iter_mut() {
+        if let Some(child) = item.as_table_mut() {
+            set_position(child, position);
+        }
+    }
+}
+
+/// Describes an argument that none of the stencil's questions ask for,
+/// suggesting the question it is closest to.
+pub fn unknown_argument(key: &str, source: &SourceConfig) -> String {
+    match closest_question(key, source) {
+        Some(variable) => {
+            format!("arguments.{key}: the stencil has no such question, did you mean '{variable}'?")
+        }
+        None => format!("arguments.{key}: the stencil has no such question"),
+    }
+}
+
+/// The question an unknown argument is likely a typo of, if any.
+pub fn closest_question<'a>(key: &str, source: &'a SourceConfig) -> Option<&'a str> {
+    source
+        .questions
+        .iter()
+        .map(|q| (edit_distance(key, &q.variable), q.variable.as_str()))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, variable)| variable)
+}
+
+// Levenshtein distance, used to point out likely typos in argument names
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            current.push(
+                (previous[j] + cost)
+                    .min(previous[j + 1] + 1)
+                    .min(current[j] + 1),
+            );
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_arguments() {
+        let source = SourceConfig::parse(
+            r#"
+            [[questions]]
+            variable = "python_version"
+            question = "Which Python version?"
+            required = true
+
+            [[questions]]
+            variable = "port"
+            question = "Which port?"
+            datatype = "int"
+            min = 1024
+            "#,
+        )
+        .unwrap();
+
+        let mut config = TargetConfig::new("my_project", "./src");
+        config
+            .arguments
+            .insert("pyhton_version".to_string(), "3.13".into());
+        config.arguments.insert("port".to_string(), "80".into());
+
+        let err = config.validate_arguments(&source).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Validation error: invalid arguments:\n  \
+             arguments.python_version: a value is required\n  \
+             arguments.port: expected an integer of at least 1024, got 80\n  \
+             arguments.pyhton_version: the stencil has no such question, \
+             did you mean 'python_version'? If it is no longer used, remove it \
+             with 'stencil config unset arguments.pyhton_version'"
+        );
+
+        // Likely typos are reported even when no required question is
+        // missing, while other unknown arguments are left to a warning
+        config.arguments.remove("pyhton_version");
+        config
+            .arguments
+            .insert("python_version".to_string(), "3.13".into());
+        config.arguments.insert("port".to_string(), "8080".into());
+        config.arguments.insert("prot".to_string(), "8080".into());
+        config.arguments.insert("color".to_string(), "red".into());
+        let err = config.validate_arguments(&source).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Validation error: invalid arguments:\n  \
+             arguments.prot: the stencil has no such question, did you mean 'port'? \
+             If it is no longer used, remove it with 'stencil config unset arguments.prot'"
+        );
+
+        config.arguments.remove("prot");
+        assert!(config.validate_arguments(&source).is_ok());
+
+        // A stencil without questions doesn't say which arguments it uses
+        assert!(config.validate_arguments(&SourceConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_secret_arguments() {
+        let config: TargetConfig = toml::from_str(
+            r#"
+            [stencil]
+            version = "1"
+
+            [project]
+            name = "my_project"
+            src = "./src"
+
+            [arguments]
+            region = "us-east-1"
+            dsn = { env = "STENCIL_TEST_SECRET_DSN" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.arguments["dsn"],
+            ArgumentValue::Env {
+                env: "STENCIL_TEST_SECRET_DSN".to_string()
+            }
+        );
+        assert!(config.resolved_arguments().is_err());
+
+        // Tests run in parallel, so the variable is one no other test reads
+        // and it is removed again as soon as it has been resolved
+        env::set_var("STENCIL_TEST_SECRET_DSN", "https://secret@example.com");
+        let resolved = config.resolved_arguments().unwrap();
+        let secrets = config.secrets();
+        env::remove_var("STENCIL_TEST_SECRET_DSN");
+        assert_eq!(resolved["dsn"], "https://secret@example.com");
+        assert_eq!(resolved["region"], "us-east-1");
+        assert_eq!(secrets, vec!["https://secret@example.com"]);
+
+        let saved = toml::to_string(&config).unwrap();
+        assert!(!saved.contains("https://secret@example.com"));
+        assert_eq!(
+            config.arguments["dsn"].to_string(),
+            "******** (from env STENCIL_TEST_SECRET_DSN)"
+        );
+    }
+
+    #[test]
+    fn test_layers() {
+        let mut config = TargetConfig::new("my_project", "./src");
+        config
+            .arguments
+            .insert("region".to_string(), "us-east-1".into());
+        config.arguments.insert("tier".to_string(), "small".into());
+        config
+            .arguments
+            .insert("unrecorded".to_string(), "x".into());
+
+        config
+            .apply_environment([
+                ("STENCIL_ARG_REGION".to_string(), "eu-west-1".to_string()),
+                ("STENCIL_ARG_TIER".to_string(), "medium".to_string()),
+                ("STENCIL_PROJECT_SRC".to_string(), "gh://o/r".to_string()),
+                ("STENCIL_UNRELATED".to_string(), "ignored".to_string()),
+            ])
+            .unwrap();
+        config
+            .apply_overrides(vec!["arguments.tier=large".to_string()])
+            .unwrap();
+
+        assert_eq!(
+            config.explain(),
+            vec![
+                (
+                    "stencil.version".into(),
+                    "1".into(),
+                    Some(Layer::ConfigFile)
+                ),
+                (
+                    "project.name".into(),
+                    "my_project".into(),
+                    Some(Layer::ConfigFile)
+                ),
+                (
+                    "project.src".into(),
+                    "gh://o/r".into(),
+                    Some(Layer::Environment("STENCIL_PROJECT_SRC".into()))
+                ),
+                (
+                    "arguments.region".into(),
+                    "eu-west-1".into(),
+                    Some(Layer::Environment("STENCIL_ARG_REGION".into()))
+                ),
+                (
+                    "arguments.tier".into(),
+                    "large".into(),
+                    Some(Layer::Override)
+                ),
+                ("arguments.unrecorded".into(), "x".into(), None),
+            ]
+        );
+
+        let err = config
+            .apply_overrides(vec!["project.nmae=typo".to_string()])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid override : unknown key 'project.nmae'"
+        );
+    }
+
+    #[test]
+    fn test_embedded_config() {
+        let dir = env::temp_dir().join(format!("stencil-embedded-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let manifest = dir.join("Cargo.toml");
+        fs::write(
+            &manifest,
+            "# my crate\n[package]\nname = \"my_crate\" # keep me\n\n[dependencies]\nserde = \"1\"\n",
+        )
+        .unwrap();
+        assert_eq!(discover(&dir), None);
+
+        let mut config = TargetConfig::new("my_project", "./src");
+        config.arguments.insert("license".to_string(), "MIT".into());
+        config.save(&manifest).unwrap();
+
+        let contents = fs::read_to_string(&manifest).unwrap();
+        assert!(contents.starts_with("# my crate\n[package]\nname = \"my_crate\" # keep me\n"));
+        assert!(contents.contains("[package.metadata.stencil.arguments]\nlicense = \"MIT\"\n"));
+        assert!(contents.contains("serde = \"1\"\n\n[package.metadata.stencil.stencil]\n"));
+        assert_eq!(discover(&dir), Some(manifest.clone()));
+
+        let loaded = TargetConfig::load(manifest.to_str().unwrap()).unwrap();
+        assert_eq!(loaded.project.name, "my_project");
+        assert_eq!(loaded.arguments["license"], "MIT".into());
+
+        // Saving again replaces the table in place
+        loaded.save(&manifest).unwrap();
+        assert_eq!(fs::read_to_string(&manifest).unwrap(), contents);
+
+        let pyproject = dir.join("pyproject.toml");
+        fs::write(&pyproject, "[project]\nname = \"my_project\"\n").unwrap();
+        assert!(TargetConfig::load(pyproject.to_str().unwrap()).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_preserves_formatting() {
+        let dir = env::temp_dir().join(format!("stencil-save-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CONFIG_FILENAME);
+        fs::write(
+            &path,
+            r#"# Managed by stencil
+[stencil]
+version = "1"
+
+[project]
+name = "my_project"
+src = "./src"
+owner = "platform-team"
+
+[arguments]
+# 3.12 until the base image is updated
+python_version = "3.12"
+region = "us-east-1"  # closest to our users
+old = "value"
+"#,
+        )
+        .unwrap();
+
+        let mut config = TargetConfig::load(path.to_str().unwrap()).unwrap();
+        config.arguments.remove("old");
+        config
+            .arguments
+            .insert("region".to_string(), "eu-west-1".into());
+        config.arguments.insert("license".to_string(), "MIT".into());
+        config
+            .generated
+            .insert("secret_key".to_string(), "abc".to_string());
+        config.save(&path).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            r#"# Managed by stencil
+[stencil]
+version = "1"
+
+[project]
+name = "my_project"
+src = "./src"
+owner = "platform-team"
+
+[arguments]
+# 3.12 until the base image is updated
+python_version = "3.12"
+region = "eu-west-1"  # closest to our users
+license = "MIT"
+
+[generated]
+secret_key = "abc"
+"#
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_get_and_unset() {
+        let mut config = TargetConfig::new("my_project", "./src");
+        config
+            .set(
+                "arguments.region",
+                "us-east-1".to_string(),
+                Layer::ConfigFile,
+            )
+            .unwrap();
+
+        assert_eq!(config.get("project.src").as_deref(), Some("./src"));
+        assert_eq!(config.get("arguments.region").as_deref(), Some("us-east-1"));
+        assert_eq!(config.get("arguments.tier"), None);
+
+        config.unset("arguments.region").unwrap();
+        assert!(config.arguments.is_empty());
+        assert_eq!(
+            config.unset("arguments.region").unwrap_err().to_string(),
+            "Validation error: 'arguments.region' is not set"
+        );
+        assert_eq!(
+            config.unset("project.src").unwrap_err().to_string(),
+            "Validation error: 'project.src' is required and can't be unset"
+        );
+        assert_eq!(
+            config.unset("project.bogus").unwrap_err().to_string(),
+            "Validation error: unknown key 'project.bogus'"
+        );
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut table: toml::Table =
+            toml::from_str("[project]\nname = \"my_project\"\nsrc = \"./src\"\n").unwrap();
+        assert_eq!(migrate(&mut table).unwrap(), 0);
+        let config: TargetConfig = toml::Value::Table(table).try_into().unwrap();
+        assert_eq!(config.stencil.version, SCHEMA_VERSION.to_string());
+        assert!(config.arguments.is_empty());
+
+        let mut table: toml::Table = toml::from_str("[stencil]\nversion = \"99\"\n").unwrap();
+        assert_eq!(
+            migrate(&mut table).unwrap_err().to_string(),
+            "Validation error: the config uses schema version 99 but this stencil only \
+             supports up to version 1; upgrade stencil to use it"
+        );
+
+        let mut table: toml::Table = toml::from_str("[stencil]\nversion = \"one\"\n").unwrap();
+        assert!(migrate(&mut table).is_err());
+    }
+
+    #[test]
+    fn test_find_project() {
+        let root = env::temp_dir().join(format!("stencil-find-{}", std::process::id()));
+        let nested = root.join("src").join("my_project");
+        fs::create_dir_all(&nested).unwrap();
+        TargetConfig::new("my_project", "./src")
+            .save(&root.join(CONFIG_FILENAME))
+            .unwrap();
+
+        assert_eq!(
+            find_project(&nested),
+            Some((root.join(CONFIG_FILENAME), root.clone()))
+        );
+        assert_eq!(
+            find_project(&root),
+            Some((root.join(CONFIG_FILENAME), root.clone()))
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_profiles() {
+        let mut config: TargetConfig = toml::from_str(
+            r#"
+            [stencil]
+            version = "1"
+
+            [project]
+            name = "my_project"
+            src = "./src"
+
+            [arguments]
+            region = "us-east-1"
+            replicas = "1"
+
+            [profiles.production]
+            dest = "deploy/production"
+
+            [profiles.production.arguments]
+            replicas = "3"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.output_dir(Path::new("repo")).unwrap(),
+            PathBuf::from("repo")
+        );
+        assert_eq!(
+            config.apply_profile("staging").unwrap_err().to_string(),
+            "Validation error: unknown profile 'staging', expected one of production"
+        );
+
+        config.apply_profile("production").unwrap();
+        let resolved = config.resolved_arguments().unwrap();
+        assert_eq!(resolved["region"], "us-east-1");
+        assert_eq!(resolved["replicas"], "3");
+        assert_eq!(
+            config.origins["arguments.replicas"],
+            Layer::Profile("production".to_string())
+        );
+        assert_eq!(
+            config.output_dir(Path::new("repo")).unwrap(),
+            Path::new("repo").join("deploy/production")
+        );
+
+        for dest in ["/tmp/x", "../x", "deploy/../../x", "./x", ""] {
+            config.profiles.get_mut("production").unwrap().dest = Some(dest.to_string());
+            assert_eq!(
+                config.output_dir(Path::new("repo")).unwrap_err().to_string(),
+                format!(
+                    "Validation error: profiles.production.dest: '{dest}' must be a relative path inside the project"
+                )
+            );
+        }
+    }
+}
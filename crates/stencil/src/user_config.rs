@@ -0,0 +1,158 @@
+// Copyright (c) 2025 David Stanek <dstanek@dstanek.com>
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use termcolor::ColorChoice;
+
+use stencil_error::StencilError;
+
+/// Per-user settings from `~/.config/stencil/config.toml`. They are loaded
+/// before any project config and only ever provide defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserConfig {
+    pub author_name: Option<String>,
+    pub author_email: Option<String>,
+    pub color: Option<ColorPreference>,
+    pub pager: Option<String>,
+    pub no_diff: bool,
+    /// Default answers that take precedence over the stencil's own defaults.
+    pub arguments: BTreeMap<String, String>,
+    /// Credentials keyed by host, like `github.com`.
+    pub credentials: BTreeMap<String, Credential>,
+    /// Short names for stencil sources, used as `alias:path/in/source`.
+    pub registry: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Credential {
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorPreference {
+    Auto,
+    Always,
+    Never,
+}
+
+impl UserConfig {
+    /// The user config location, honoring `XDG_CONFIG_HOME`.
+    pub fn path() -> Option<PathBuf> {
+        let config_home = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(config_home.join("stencil").join("config.toml"))
+    }
+
+    /// Loads the user config, which is entirely optional. Errors name the
+    /// file so a broken user config is easy to find.
+    pub fn load() -> Result<Self, StencilError> {
+        match Self::path() {
+            Some(path) if path.exists() => {
+                let error = |e: &dyn std::fmt::Display| {
+                    StencilError::ConfigValidation(format!("user config {}: {e}", path.display()))
+                };
+                let contents = fs::read_to_string(&path).map_err(|e| error(&e))?;
+                toml::from_str(&contents).map_err(|e| error(&e))
+            }
+            _ => Ok(UserConfig::default()),
+        }
+    }
+
+    pub fn color_choice(&self, default: ColorChoice) -> ColorChoice {
+        match self.color {
+            Some(ColorPreference::Auto) => ColorChoice::Auto,
+            Some(ColorPreference::Always) => ColorChoice::Always,
+            Some(ColorPreference::Never) => ColorChoice::Never,
+            None => default,
+        }
+    }
+
+    pub fn token_for(&self, host: &str) -> Option<String> {
+        self.credentials.get(host).map(|c| c.token.clone())
+    }
+
+    /// Expands a registry alias at the start of a stencil source. Sources
+    /// that don't start with a known alias and a `:` are returned unchanged,
+    /// so the `:` in a source like `gh://org/repo/sub` is never mistaken for
+    /// one.
+    pub fn resolve_source(&self, src: &str) -> String {
+        let expansion = self
+            .registry
+            .iter()
+            .filter_map(|(alias, base)| {
+                let rest = src.strip_prefix(alias.as_str())?.strip_prefix(':')?;
+                Some((alias.len(), base, rest))
+            })
+            .max_by_key(|(len, _, _)| *len);
+        match expansion {
+            Some((_, base, "")) => base.clone(),
+            Some((_, base, rest)) => format!("{}/{}", base.trim_end_matches('/'), rest),
+            None => src.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config: UserConfig = toml::from_str(
+            r#"
+            author_name = "Jane Doe"
+            color = "never"
+            no_diff = true
+
+            [arguments]
+            license = "Apache-2.0"
+
+            [credentials."github.com"]
+            token = "ghp_secret"
+
+            [registry]
+            mine = "gh://jane/stencils/"
+            "mine:rust" = "gh://jane/rust-stencils"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.author_name.as_deref(), Some("Jane Doe"));
+        assert_eq!(config.color_choice(ColorChoice::Always), ColorChoice::Never);
+        assert!(config.no_diff);
+        assert_eq!(
+            config.token_for("github.com").as_deref(),
+            Some("ghp_secret")
+        );
+        assert_eq!(config.token_for("gitlab.com"), None);
+        assert_eq!(
+            config.resolve_source("mine:python"),
+            "gh://jane/stencils/python"
+        );
+        assert_eq!(config.resolve_source("mine:"), "gh://jane/stencils/");
+        assert_eq!(config.resolve_source("gh://jane/other"), "gh://jane/other");
+        assert_eq!(
+            config.resolve_source("gh://org/repo/sub"),
+            "gh://org/repo/sub"
+        );
+        assert_eq!(
+            config.resolve_source("mine:python/sub"),
+            "gh://jane/stencils/python/sub"
+        );
+        assert_eq!(
+            config.resolve_source("mine:rust:cli"),
+            "gh://jane/rust-stencils/cli"
+        );
+
+        assert!(toml::from_str::<UserConfig>("colour = \"never\"").is_err());
+    }
+}
@@ -45,6 +45,7 @@ class Stencil:
         self._command_args: ArgList = []
         self._dest: Path|None = None
         self._src: str|None = None
+        self._cwd: Path|None = None
 
     @classmethod
     def init(cls) -> Self:
@@ -66,6 +67,10 @@ class Stencil:
         self._src = src
         return self
 
+    def cwd(self, cwd: Path) -> Self:
+        self._cwd = cwd
+        return self
+
     def override(self, name: str, value: str) -> Self:
         self._stencil_args.extend(["--override", f"{name}={value}"])
         return self
@@ -83,7 +88,7 @@ class Stencil:
             cmd.append(f"{self._dest}")
         if self._src:
             cmd.append(self._src)
-        return Result(self._dest, run(cmd, capture_output=True, text=True))
+        return Result(self._dest, run(cmd, capture_output=True, text=True, cwd=self._cwd))
 
 
 def slurp(directory: Path) -> dict[str, str]:
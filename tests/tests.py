@@ -1,5 +1,6 @@
 # Copyright (c) 2024-2025 David Stanek <dstanek@dstanek.com>
 
+from os.path import relpath
 from pathlib import Path
 from textwrap import dedent
 
@@ -60,7 +61,7 @@ def describe_stencil_init():
 
                     [project]
                     name = "{PROJECT_NAME}"
-                    src = "{STENCIL_PATH}"
+                    src = "{relpath(STENCIL_PATH, stencil_init.dest)}"
 
                     [arguments]
                     arg0 = "0"
@@ -106,7 +107,7 @@ def describe_stencil_lifecycle():
         def it_creates_files(stencil_init):
             files = slurp(stencil_init.dest)
             expected_files = {
-                ".stencil.toml": f'[stencil]\nversion = "1"\n\n[project]\nname = "my_project"\nsrc = "{STENCIL_PATH}"\n\n[arguments]\n',
+                ".stencil.toml": f'[stencil]\nversion = "1"\n\n[project]\nname = "my_project"\nsrc = "{relpath(STENCIL_PATH, stencil_init.dest)}"\n\n[arguments]\n',
                 "README.md": "# my_project Documentation\n\nA\nB\nC\n",
                 "pyproject.toml": "[project]\nname = my_project\n",
                 ".github/CODEOWNERS": "* @all_the_engineers\n",
@@ -191,7 +192,7 @@ def describe_stencil_lifecycle():
         def it_creates_and_updates_files(stencil_init):
             files = slurp(Path(stencil_init.dest))
             expected_files = {
-                ".stencil.toml": f'[stencil]\nversion = "1"\n\n[project]\nname = "my_project"\nsrc = "{STENCIL_PATH}"\n\n[arguments]\n',
+                ".stencil.toml": f'[stencil]\nversion = "1"\n\n[project]\nname = "my_project"\nsrc = "{relpath(STENCIL_PATH, stencil_init.dest)}"\n\n[arguments]\n',
                 "README.md": "# my_project Documentation\n\nA\nX\nC\n",
                 "pyproject.toml": "[project]\nname = my_project\n",
                 ".github/CODEOWNERS": "* @all_the_engineers\n* @all_the_managers\n",
@@ -203,6 +204,24 @@ def describe_stencil_lifecycle():
             assert files == expected_files
 
 
+def describe_running_from_a_nested_directory():
+
+    STENCIL_PATH = "./stencil1_src/stencil"
+
+    @pytest.fixture(scope="module")
+    def stencil_plan(tmp_path_factory):
+        tmp_path = tmp_path_factory.mktemp("output") / "output"
+        Stencil.init().dest(tmp_path).src(STENCIL_PATH).run().returncode(0)
+        yield (Stencil.plan()
+            .cwd(tmp_path / "my_project")
+            .run())
+
+    def it_finds_the_project_and_its_stencil(stencil_plan):
+        (stencil_plan
+            .returncode(0)
+            .stdout_contains("Planning "))
+
+
 @pytest.mark.github
 def describe_github_stencil():
 